@@ -0,0 +1,24 @@
+#!/bin/sh
+# CA local + certificado do WebSocket interno (api01, api02, localhost), para testar wss:// sem internet
+# uso: ./gen-ws-certs.sh [diretório]   (padrão: ./certs)
+set -e
+
+DIR=${1:-certs}
+mkdir -p "$DIR"
+
+openssl req -x509 -newkey rsa:2048 -nodes -days 3650 \
+  -subj "/CN=rinha-ws-ca" \
+  -keyout "$DIR/ca.key" -out "$DIR/ca.pem"
+
+openssl req -newkey rsa:2048 -nodes \
+  -subj "/CN=rinha-ws" \
+  -keyout "$DIR/ws.key" -out "$DIR/ws.csr"
+
+printf "subjectAltName=DNS:api01,DNS:api02,DNS:localhost,IP:127.0.0.1\nextendedKeyUsage=serverAuth\n" > "$DIR/ws.ext"
+
+openssl x509 -req -days 825 -in "$DIR/ws.csr" \
+  -CA "$DIR/ca.pem" -CAkey "$DIR/ca.key" -CAcreateserial \
+  -extfile "$DIR/ws.ext" -out "$DIR/ws.pem"
+
+rm -f "$DIR/ws.csr" "$DIR/ws.ext" "$DIR/ca.srl"
+echo "WS_TLS_CERT=$DIR/ws.pem WS_TLS_KEY=$DIR/ws.key WS_TLS_CA=$DIR/ca.pem"
@@ -0,0 +1,58 @@
+use crate::infrastructure::config::ADMIN_TOKEN;
+use axum::extract::Request;
+use axum::http::{HeaderMap, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+
+pub const ADMIN_TOKEN_HEADER: &str = "x-admin-token";
+
+// compara sem sair no primeiro byte diferente, para o tempo de resposta não entregar o token
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// sem token configurado nenhuma chamada passa
+pub fn admin_authorized(expected: Option<&str>, headers: &HeaderMap) -> bool {
+    let Some(expected) = expected else {
+        return false;
+    };
+    headers.get(ADMIN_TOKEN_HEADER)
+        .is_some_and(|token| constant_time_eq(token.as_bytes(), expected.as_bytes()))
+}
+
+// as rotas /admin ficam na mesma porta pública do balanceador, então exigem o token
+pub async fn require_admin_token(request: Request, next: Next) -> Result<Response, StatusCode> {
+    if !admin_authorized(ADMIN_TOKEN.as_deref(), request.headers()) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    Ok(next.run(request).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{admin_authorized, ADMIN_TOKEN_HEADER};
+    use axum::http::HeaderMap;
+
+    fn headers(token: Option<&str>) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(token) = token {
+            headers.insert(ADMIN_TOKEN_HEADER, token.parse().unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn accepts_only_the_configured_token() {
+        assert!(admin_authorized(Some("segredo"), &headers(Some("segredo"))));
+        assert!(!admin_authorized(Some("segredo"), &headers(Some("segredo2"))));
+        assert!(!admin_authorized(Some("segredo"), &headers(Some("segred"))));
+        assert!(!admin_authorized(Some("segredo"), &headers(Some(""))));
+        assert!(!admin_authorized(Some("segredo"), &headers(None)));
+    }
+
+    #[test]
+    fn without_configured_token_everything_is_refused() {
+        assert!(!admin_authorized(None, &headers(Some("segredo"))));
+        assert!(!admin_authorized(None, &headers(None)));
+    }
+}
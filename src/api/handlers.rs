@@ -1,24 +1,34 @@
-use crate::domain::entities::{AppState, PaymentsSummary, PaymentsSummaryFilter, SummaryData};
-use crate::infrastructure::{date_to_ts, round2};
+use crate::domain::entities::{
+    ApiError, AppState, DeadLetter, PaymentRecord, PaymentsQueryFilter, PaymentsSummary, PaymentsSummaryFilter, PostPayments,
+    QueuedPayment, SummaryBucket, SummaryData,
+};
+use crate::domain::money::Money;
+use crate::domain::time_range::{parse_interval, TimeRange};
+use crate::infrastructure::config::{processor_fee, GLOBAL_HEALTH_STATUS, LEGACY_PROCESSORS, MAX_TIMESTAMP_MS, PROCESSORS};
+use crate::infrastructure::health::{health_is_stale, DEGRADED_MODE};
+use crate::infrastructure::utils::now_unix_ms;
+use crate::infrastructure::{circuit_states, instance_metrics, is_leader, outcome_counters, processor_stats, HEALTH_BROADCASTER};
 use axum::body::Bytes;
-use axum::extract::{Query, State};
+use axum::extract::rejection::QueryRejection;
+use axum::extract::{Path, Query, State};
 use axum::{
     http::StatusCode,
     Json,
 };
-use redis::aio::ConnectionManager;
-use redis::AsyncCommands;
+use chrono::{DateTime, SecondsFormat};
+use std::collections::BTreeMap;
 use std::string::String;
 
-pub async fn clear_redis(
+pub async fn purge_payments(
     State(state): State<AppState>
 ) -> StatusCode {
-    let mut conn = (*state.redis).clone();
-    match AsyncCommands::flushall::<String>(&mut conn).await {
+    let names: Vec<&str> = PROCESSORS.iter().map(|p| p.name.as_str()).collect();
+    match state.store.purge(&names).await.and(state.queue.purge_accepted().await) {
         Ok(_) => {
             StatusCode::OK
         },
-        Err(_) => {
+        Err(e) => {
+            eprintln!("Erro ao apagar os pagamentos: {}", e);
             StatusCode::INTERNAL_SERVER_ERROR
         }
     }
@@ -29,63 +39,153 @@ pub async fn payments(
     //Json(payload): Json<PostPayments>,
     body: Bytes,
 ) -> StatusCode {
-    match state.sender.send(body) {
-        Ok(_) => StatusCode::CREATED,
-        Err(_) =>  StatusCode::TOO_MANY_REQUESTS,
+    let payment = match serde_json::from_slice::<PostPayments>(&body) {
+        Ok(payment) => payment,
+        Err(_) => return StatusCode::BAD_REQUEST,
+    };
+    // só o que o cliente pode mandar vai para a fila: tentativas, histórico e requestedAt são nossos
+    let correlation_id = payment.correlation_id.clone();
+    let queued = QueuedPayment {
+        payment,
+        attempts: 0,
+        processor_attempts: Default::default(),
+        // o prazo total das tentativas conta daqui, incluindo o tempo esperando na fila
+        enqueued_at: now_unix_ms(),
+        requested_at: 0,
+        history: vec![],
+    };
+    let payload = match serde_json::to_vec(&queued) {
+        Ok(payload) => payload,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    match state.queue.enqueue(&correlation_id, &payload).await {
+        Ok(true) => StatusCode::CREATED,
+        // correlationId já aceito antes, responde sempre igual sem enfileirar de novo
+        Ok(false) => StatusCode::OK,
+        Err(e) => {
+            eprintln!("Erro ao enfileirar pagamento: {}", e);
+            StatusCode::SERVICE_UNAVAILABLE
+        }
     }
 }
 
+type ApiResult<T> = Result<Json<T>, (StatusCode, Json<ApiError>)>;
+
+fn api_error(status: StatusCode, field: Option<&str>, error: String) -> (StatusCode, Json<ApiError>) {
+    (status, Json(ApiError { error, field: field.map(|f| f.to_string()) }))
+}
+
 pub async fn payments_summary(
-    Query(params): Query<PaymentsSummaryFilter>,
+    params: Result<Query<PaymentsSummaryFilter>, QueryRejection>,
     State(state): State<AppState>,
-) -> (StatusCode, Json<PaymentsSummary>) {
-    let mut conn = (*state.redis).clone();
+) -> ApiResult<PaymentsSummary> {
+    let Query(params) = params.map_err(|e| api_error(StatusCode::BAD_REQUEST, None, e.body_text()))?;
 
-    let from = date_to_ts(params.from.as_deref().unwrap_or("1970-01-01T00:00:00Z").parse().unwrap());
-    let to = date_to_ts(params.to.as_deref().unwrap_or("1970-01-01T00:00:00Z").parse().unwrap());
+    let range = TimeRange::resolve(
+        params.from.as_deref(),
+        params.to.as_deref(),
+        params.bounds.as_deref(),
+        now_unix_ms() as i64,
+        MAX_TIMESTAMP_MS,
+    ).map_err(|e| api_error(StatusCode::BAD_REQUEST, e.field, e.message))?;
+    let interval = parse_interval(params.group_by.as_deref(), params.interval.as_deref())
+        .map_err(|e| api_error(StatusCode::BAD_REQUEST, e.field, e.message))?;
 
-    let (amounts_default, amounts_fallback) = get_summary_data(&mut conn, from, to).await;
+    let names: Vec<&str> = PROCESSORS.iter().map(|p| p.name.as_str()).collect();
+    let totals = state.store.summary(&names, range.from, range.to).await.map_err(|e| {
+        eprintln!("Erro ao calcular o summary: {}", e);
+        api_error(StatusCode::INTERNAL_SERVER_ERROR, None, "erro ao consultar os pagamentos".to_string())
+    })?;
+    let processors = summary_map(&names, totals);
 
-    let summary = PaymentsSummary {
-        default: SummaryData {
-            total_requests: amounts_default.len() as i64,
-            total_amount: round2(amounts_default.iter().sum::<f64>()),
-        },
-        fallback: SummaryData {
-            total_requests: amounts_fallback.len() as i64,
-            total_amount: round2(amounts_fallback.iter().sum::<f64>()),
-        },
+    let series = match interval {
+        None => None,
+        Some(interval) => {
+            let points = state.store.series(&names, range.from, range.to, interval).await.map_err(|e| {
+                eprintln!("Erro ao calcular a série do summary: {}", e);
+                api_error(StatusCode::INTERNAL_SERVER_ERROR, None, "erro ao consultar os pagamentos".to_string())
+            })?;
+            Some(points.into_iter()
+                .map(|(start, totals)| SummaryBucket {
+                    from: format_ts(start),
+                    processors: summary_map(&names, totals),
+                })
+                .collect())
+        }
     };
 
-    (StatusCode::OK, Json(summary))
+    Ok(Json(PaymentsSummary { processors, series }))
+}
 
-    /*let mut conn = (*state.redis).clone();
-    let from = date_to_ts(params.from.clone().unwrap_or_else(|| "1970-01-01T00:00:00Z".to_string()));
-    let to = date_to_ts(params.to.clone().unwrap_or_else(|| "1970-01-01T00:00:00Z".to_string()));
+pub async fn get_payment(
+    Path(correlation_id): Path<String>,
+    State(state): State<AppState>,
+) -> ApiResult<PaymentRecord> {
+    match state.store.lookup(&correlation_id).await {
+        Ok(Some(record)) => Ok(Json(record)),
+        Ok(None) => Err(api_error(StatusCode::NOT_FOUND, None, format!("pagamento {} não contabilizado", correlation_id))),
+        Err(e) => {
+            eprintln!("Erro ao buscar o pagamento {}: {}", correlation_id, e);
+            Err(api_error(StatusCode::INTERNAL_SERVER_ERROR, None, "erro ao consultar os pagamentos".to_string()))
+        }
+    }
+}
 
-    let (amounts_default, amounts_fallback) = get_summary_data(&mut conn, from, to).await;
+pub async fn list_payments(
+    params: Result<Query<PaymentsQueryFilter>, QueryRejection>,
+    State(state): State<AppState>,
+) -> ApiResult<Vec<PaymentRecord>> {
+    let Query(params) = params.map_err(|e| api_error(StatusCode::BAD_REQUEST, None, e.body_text()))?;
+    if !PROCESSORS.iter().any(|p| p.name == params.processor) {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            Some("processor"),
+            format!("processador desconhecido: {}", params.processor),
+        ));
+    }
 
-    let sumary = PaymentsSummary {
-        default: SummaryData {
-            total_requests: amounts_default.len() as i64,
-            total_amount: round2(amounts_default.iter().copied().sum()),
-        },
-        fallback: SummaryData {
-            total_requests: amounts_fallback.len() as i64,
-            total_amount: round2(amounts_fallback.iter().copied().sum()),
-        },
-    };
-    (StatusCode::OK, Json(sumary))*/
+    let range = TimeRange::resolve(
+        params.from.as_deref(),
+        params.to.as_deref(),
+        params.bounds.as_deref(),
+        now_unix_ms() as i64,
+        MAX_TIMESTAMP_MS,
+    ).map_err(|e| api_error(StatusCode::BAD_REQUEST, e.field, e.message))?;
+
+    state.store.query_range(&params.processor, range.from, range.to).await
+        .map(Json)
+        .map_err(|e| {
+            eprintln!("Erro ao listar os pagamentos de {}: {}", params.processor, e);
+            api_error(StatusCode::INTERNAL_SERVER_ERROR, None, "erro ao consultar os pagamentos".to_string())
+        })
+}
+
+fn summary_map(names: &[&str], totals: Vec<(u64, Money)>) -> BTreeMap<String, SummaryData> {
+    let mut map: BTreeMap<String, SummaryData> = names.iter().zip(totals)
+        .map(|(name, (count, total))| (name.to_string(), summary_data(name, count, total)))
+        .collect();
+    // quem lê o formato antigo espera default e fallback mesmo com outra lista de processadores
+    for name in LEGACY_PROCESSORS {
+        map.entry(name.to_string()).or_insert_with(|| summary_data(name, 0, Money::ZERO));
+    }
+    map
 }
 
-async fn get_summary_data(conn: &mut ConnectionManager, from: f64, to: f64) -> (Vec<f64>, Vec<f64>) {
-    //println!("{}", to);
-    let ids_default: Vec<String> = AsyncCommands::zrangebyscore(conn, "summary:default:history", from, to).await.unwrap_or_default();
-    let amounts_default: Vec<f64> = AsyncCommands::hget(conn, "summary:default:data", &ids_default).await.unwrap_or_default();
+fn format_ts(ms: i64) -> String {
+    DateTime::from_timestamp_millis(ms)
+        .map(|dt| dt.to_rfc3339_opts(SecondsFormat::Millis, true))
+        .unwrap_or_else(|| ms.to_string())
+}
 
-    let ids_fallback: Vec<String> = AsyncCommands::zrangebyscore(conn, "summary:fallback:history", from, to).await.unwrap_or_default();
-    let amounts_fallback: Vec<f64> = AsyncCommands::hget(conn, "summary:fallback:data", &ids_fallback).await.unwrap_or_default();
-    (amounts_default, amounts_fallback)
+// taxa sobre o total em centavos, igual ao que o processador contabiliza
+fn summary_data(processor: &str, count: u64, total_amount: Money) -> SummaryData {
+    let total_fee = total_amount.fee(processor_fee(processor));
+    SummaryData {
+        total_requests: count as i64,
+        total_amount,
+        total_fee,
+        net_amount: total_amount - total_fee,
+    }
 }
 
 pub async fn compare_summary(host: String, filter: &PaymentsSummaryFilter) -> SummaryData {
@@ -101,12 +201,297 @@ pub async fn compare_summary(host: String, filter: &PaymentsSummaryFilter) -> Su
     headers.insert("x-rinha-token", "123".parse().unwrap());
 
     let client = reqwest::Client::new();
-    return client.get(format!("{}/admin/payments-summary",host))
+    client.get(format!("{}/admin/payments-summary",host))
         .query(&querystring)
         .headers(headers)
         .send()
         .await.unwrap()
         .json::<SummaryData>()
         .await
-        .unwrap();
+        .unwrap()
+}
+
+fn dead_letter_not_found(correlation_id: &str) -> (StatusCode, Json<ApiError>) {
+    api_error(StatusCode::NOT_FOUND, None, format!("pagamento {} não está na fila de falhas", correlation_id))
+}
+
+fn dead_letter_error(context: &str, e: impl std::fmt::Display) -> (StatusCode, Json<ApiError>) {
+    eprintln!("[ADMIN] Erro ao {}: {}", context, e);
+    api_error(StatusCode::INTERNAL_SERVER_ERROR, None, "erro ao consultar a fila de falhas".to_string())
+}
+
+pub async fn list_failed_payments(
+    State(state): State<AppState>,
+) -> ApiResult<Vec<DeadLetter>> {
+    state.queue.list_dead_letters().await
+        .map(Json)
+        .map_err(|e| dead_letter_error("listar a fila de falhas", e))
+}
+
+pub async fn get_failed_payment(
+    Path(correlation_id): Path<String>,
+    State(state): State<AppState>,
+) -> ApiResult<DeadLetter> {
+    match state.queue.get_dead_letter(&correlation_id).await {
+        Ok(Some(entry)) => Ok(Json(entry)),
+        Ok(None) => Err(dead_letter_not_found(&correlation_id)),
+        Err(e) => Err(dead_letter_error("buscar o pagamento com falha", e)),
+    }
+}
+
+pub async fn replay_failed_payments(
+    State(state): State<AppState>,
+) -> ApiResult<serde_json::Value> {
+    let entries = state.queue.list_dead_letters().await
+        .map_err(|e| dead_letter_error("listar a fila de falhas", e))?;
+    let mut replayed = 0;
+    for entry in entries {
+        if state.queue.replay_dead_letter(entry).await.map_err(|e| dead_letter_error("reenfileirar o pagamento com falha", e))? {
+            replayed += 1;
+        }
+    }
+    Ok(Json(serde_json::json!({ "replayed": replayed })))
+}
+
+pub async fn replay_failed_payment(
+    Path(correlation_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, Json<ApiError>)> {
+    let entry = match state.queue.get_dead_letter(&correlation_id).await {
+        Ok(Some(entry)) => entry,
+        Ok(None) => return Err(dead_letter_not_found(&correlation_id)),
+        Err(e) => return Err(dead_letter_error("buscar o pagamento com falha", e)),
+    };
+    match state.queue.replay_dead_letter(entry).await {
+        Ok(true) => Ok(StatusCode::ACCEPTED),
+        // outra chamada reenfileirou ou descartou no meio tempo
+        Ok(false) => Err(dead_letter_not_found(&correlation_id)),
+        Err(e) => Err(dead_letter_error("reenfileirar o pagamento com falha", e)),
+    }
+}
+
+pub async fn discard_failed_payments(
+    State(state): State<AppState>,
+) -> ApiResult<serde_json::Value> {
+    state.queue.discard_all_dead_letters().await
+        .map(|discarded| Json(serde_json::json!({ "discarded": discarded })))
+        .map_err(|e| dead_letter_error("descartar a fila de falhas", e))
+}
+
+pub async fn discard_failed_payment(
+    Path(correlation_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, (StatusCode, Json<ApiError>)> {
+    match state.queue.discard_dead_letter(&correlation_id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(dead_letter_not_found(&correlation_id)),
+        Err(e) => Err(dead_letter_error("descartar o pagamento com falha", e)),
+    }
+}
+
+pub async fn metrics() -> Json<serde_json::Value> {
+    let health = GLOBAL_HEALTH_STATUS.read().await.clone();
+    Json(serde_json::json!({
+        "health": {
+            "leader": is_leader(),
+            "updatedAt": health.updated_at,
+            "stale": health_is_stale(&health),
+            "degradedMode": DEGRADED_MODE.as_str(),
+            "broadcaster": HEALTH_BROADCASTER.name(),
+        },
+        "outcomes": outcome_counters(),
+        "circuits": circuit_states(),
+        "stats": &*processor_stats(),
+        "instances": instance_metrics(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        discard_failed_payment, get_failed_payment, get_payment, list_payments, payments, payments_summary,
+        replay_failed_payment, summary_map,
+    };
+    use crate::domain::entities::{AppState, PaymentsQueryFilter, PaymentsSummaryFilter, QueuedPayment};
+    use crate::domain::money::Money;
+    use crate::domain::store::PaymentStore;
+    use crate::infrastructure::config::processor_fee;
+    use crate::infrastructure::{MemoryPaymentQueue, MemoryPaymentStore};
+    use axum::body::Bytes;
+    use axum::extract::{Path, Query, State};
+    use axum::http::StatusCode;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    // 2025-07-01T12:00:00.000Z
+    const NOON: i64 = 1_751_371_200_000;
+
+    async fn state() -> AppState {
+        let store = MemoryPaymentStore::new();
+        store.record("default", "a", Money::from_cents(1000), NOON).await.unwrap();
+        store.record("default", "b", Money::from_cents(990), NOON + 500).await.unwrap();
+        store.record("fallback", "c", Money::from_cents(2000), NOON + 1_500).await.unwrap();
+        AppState { store: Arc::new(store), queue: Arc::new(MemoryPaymentQueue::new()) }
+    }
+
+    fn summary_filter(from: Option<&str>, to: Option<&str>) -> PaymentsSummaryFilter {
+        PaymentsSummaryFilter {
+            from: from.map(str::to_string),
+            to: to.map(str::to_string),
+            bounds: None,
+            group_by: None,
+            interval: None,
+        }
+    }
+
+    fn query_filter(processor: &str, from: Option<&str>, to: Option<&str>) -> PaymentsQueryFilter {
+        PaymentsQueryFilter {
+            processor: processor.to_string(),
+            from: from.map(str::to_string),
+            to: to.map(str::to_string),
+            bounds: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn summary_totals_and_fees_per_processor() {
+        let summary = payments_summary(Ok(Query(summary_filter(None, None))), State(state().await)).await.unwrap().0;
+        let default = &summary.processors["default"];
+        assert_eq!(default.total_requests, 2);
+        assert_eq!(default.total_amount, Money::from_cents(1990));
+        assert_eq!(default.total_fee, Money::from_cents(1990).fee(processor_fee("default")));
+        assert_eq!(default.net_amount, default.total_amount - default.total_fee);
+        assert_eq!(summary.processors["fallback"].total_requests, 1);
+        assert!(summary.series.is_none());
+    }
+
+    #[test]
+    fn summary_keeps_default_and_fallback_with_other_processors() {
+        let summary = summary_map(&["primary"], vec![(3, Money::from_cents(3000))]);
+        assert_eq!(summary.keys().collect::<Vec<_>>(), ["default", "fallback", "primary"]);
+        assert_eq!(summary["primary"].total_requests, 3);
+        assert_eq!(summary["default"].total_requests, 0);
+        assert_eq!(summary["fallback"].total_amount, Money::ZERO);
+    }
+
+    #[tokio::test]
+    async fn summary_range_is_inclusive() {
+        let filter = summary_filter(Some("2025-07-01T12:00:00.500Z"), Some("2025-07-01T12:00:01.500Z"));
+        let summary = payments_summary(Ok(Query(filter)), State(state().await)).await.unwrap().0;
+        assert_eq!(summary.processors["default"].total_amount, Money::from_cents(990));
+        assert_eq!(summary.processors["fallback"].total_amount, Money::from_cents(2000));
+    }
+
+    #[tokio::test]
+    async fn summary_series_by_second() {
+        let filter = PaymentsSummaryFilter { group_by: Some("second".to_string()), ..summary_filter(None, None) };
+        let series = payments_summary(Ok(Query(filter)), State(state().await)).await.unwrap().0.series.unwrap();
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[0].from, "2025-07-01T12:00:00.000Z");
+        assert_eq!(series[0].processors["default"].total_requests, 2);
+        assert_eq!(series[0].processors["fallback"].total_requests, 0);
+        assert_eq!(series[1].from, "2025-07-01T12:00:01.000Z");
+        assert_eq!(series[1].processors["fallback"].total_requests, 1);
+    }
+
+    #[tokio::test]
+    async fn summary_rejects_invalid_parameters() {
+        let (status, error) = payments_summary(Ok(Query(summary_filter(Some("ontem"), None))), State(state().await))
+            .await.unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error.field.as_deref(), Some("from"));
+
+        let filter = summary_filter(Some("2025-07-01T13:00:00Z"), Some("2025-07-01T12:00:00Z"));
+        let (status, _) = payments_summary(Ok(Query(filter)), State(state().await)).await.unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let filter = PaymentsSummaryFilter { group_by: Some("fortnight".to_string()), ..summary_filter(None, None) };
+        let (status, _) = payments_summary(Ok(Query(filter)), State(state().await)).await.unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn lookup_recorded_payment() {
+        let state = state().await;
+        let record = get_payment(Path("b".to_string()), State(state.clone())).await.unwrap().0;
+        assert_eq!(record.processor, "default");
+        assert_eq!(record.amount, Money::from_cents(990));
+        assert_eq!(record.requested_at, NOON + 500);
+
+        let (status, _) = get_payment(Path("z".to_string()), State(state)).await.unwrap_err();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn list_payments_of_a_processor_in_range() {
+        let state = state().await;
+        let records = list_payments(Ok(Query(query_filter("default", None, None))), State(state.clone())).await.unwrap().0;
+        let ids: Vec<&str> = records.iter().map(|r| r.correlation_id.as_str()).collect();
+        assert_eq!(ids, ["a", "b"]);
+
+        let filter = query_filter("default", Some("2025-07-01T12:00:00.001Z"), None);
+        let records = list_payments(Ok(Query(filter)), State(state.clone())).await.unwrap().0;
+        assert_eq!(records.len(), 1);
+
+        let (status, error) = list_payments(Ok(Query(query_filter("outro", None, None))), State(state)).await.unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(error.field.as_deref(), Some("processor"));
+    }
+
+    #[tokio::test]
+    async fn payments_are_enqueued_once() {
+        let state = state().await;
+        let body = Bytes::from_static(br#"{"correlationId":"d","amount":19.90}"#);
+        assert_eq!(payments(State(state.clone()), body.clone()).await, StatusCode::CREATED);
+        assert_eq!(payments(State(state.clone()), body).await, StatusCode::OK);
+        assert_eq!(payments(State(state), Bytes::from_static(b"{}")).await, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn payments_reject_non_positive_and_huge_amounts() {
+        let state = state().await;
+        for body in [
+            &br#"{"correlationId":"e","amount":-5}"#[..],
+            br#"{"correlationId":"e","amount":0}"#,
+            br#"{"correlationId":"e","amount":92233720368547758.07}"#,
+            br#"{"correlationId":"e","amount":19.999}"#,
+        ] {
+            assert_eq!(payments(State(state.clone()), Bytes::from_static(body)).await, StatusCode::BAD_REQUEST);
+        }
+        assert_eq!(state.queue.claim(Duration::ZERO).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn payments_drop_fields_the_client_should_not_set() {
+        let state = state().await;
+        let body = Bytes::from_static(
+            br#"{"correlationId":"x","amount":19.9,"requestedAt":1,"enqueuedAt":1,"attempts":7,"processorAttempts":{"default":99},"history":[{"processor":"default","outcome":null,"reason":"x","at":1}]}"#,
+        );
+        assert_eq!(payments(State(state.clone()), body).await, StatusCode::CREATED);
+
+        let claimed = state.queue.claim(Duration::ZERO).await.unwrap().unwrap();
+        let queued: QueuedPayment = serde_json::from_slice(&claimed).unwrap();
+        assert_eq!(queued.payment.correlation_id, "x");
+        assert_eq!(queued.payment.amount, Money::from_cents(1990));
+        assert_eq!(queued.requested_at, 0);
+        // o prazo das tentativas conta de quando a API aceitou
+        assert!(queued.enqueued_at > 1);
+        assert_eq!(queued.attempts, 0);
+        assert!(queued.processor_attempts.is_empty());
+        assert!(queued.history.is_empty());
+    }
+
+    #[tokio::test]
+    async fn unknown_dead_letter_is_a_json_not_found() {
+        let state = state().await;
+        let (status, error) = get_failed_payment(Path("z".to_string()), State(state.clone())).await.unwrap_err();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(error.error, "pagamento z não está na fila de falhas");
+        assert_eq!(error.field, None);
+
+        let (status, _) = replay_failed_payment(Path("z".to_string()), State(state.clone())).await.unwrap_err();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = discard_failed_payment(Path("z".to_string()), State(state)).await.unwrap_err();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
 }
@@ -1,4 +1,7 @@
+pub mod auth;
 pub mod handlers;
 pub use handlers::{
-    clear_redis, payments, payments_summary,
+    discard_failed_payment, discard_failed_payments, get_failed_payment, get_payment,
+    list_failed_payments, list_payments, metrics, payments, payments_summary, purge_payments, replay_failed_payment, replay_failed_payments,
 };
+pub use auth::require_admin_token;
@@ -1,53 +1,369 @@
-use crate::infrastructure::config::{PAYMENT_PROCESSOR_DEFAULT_URL, PAYMENT_PROCESSOR_FALLBACK_URL};
-use crate::infrastructure::{payments_request, store_summary};
-use crate::{AnyError, PostPayments};
-use chrono::{SecondsFormat, Utc};
-use redis::aio::ConnectionManager;
+use crate::infrastructure::config::{next_processor, processor_config, retry_policy};
+use crate::infrastructure::health::processor_timeout;
+use crate::infrastructure::utils::elapsed_since;
+use crate::infrastructure::{
+    circuit_breaker, classify_response, payments_request, record_call, record_outcome, verify_payment,
+};
+use chrono::{DateTime, SecondsFormat, Utc};
 use reqwest::Client;
 use std::sync::Arc;
-use std::time::Duration;
-use crate::domain::entities::ProcessorDecision;
-
-pub async fn process(payment_json: String, conn: Arc<ConnectionManager>, client: Arc<Client>, decision: ProcessorDecision) -> Result<(), AnyError> {
-    let mut conn = (*conn).clone();
-    let payment: PostPayments = match serde_json::from_str(&payment_json) {
-        Ok(p) => p,
-        Err(_) => return Err(format!("Erro ao deserializar JSON {}", &payment_json).into()),
-    };
+use std::time::{Duration, Instant};
+use crate::domain::entities::{PaymentVerification, ProcessError, ProcessorDecision, ProcessorOutcome, ProcessorPayment, QueuedPayment};
+use crate::domain::store::PaymentStore;
+
+// erro de infraestrutura (redis etc): tenta de novo em pouco tempo
+const INFRA_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+fn process_error(processor: Option<&str>, reason: String) -> ProcessError {
+    ProcessError {
+        processor: processor.map(|p| p.to_string()),
+        outcome: None,
+        reason,
+        retry_after: Some(INFRA_RETRY_DELAY),
+    }
+}
+
+fn outcome_error(processor: &str, outcome: ProcessorOutcome, reason: String, retry_after: Option<Duration>) -> ProcessError {
+    ProcessError {
+        processor: Some(processor.to_string()),
+        outcome: Some(outcome),
+        reason,
+        retry_after,
+    }
+}
+
+fn processor_url(processor: &str) -> String {
+    processor_config(processor).map(|p| p.url.clone()).unwrap_or_default()
+}
 
+fn found_timestamp(found: &ProcessorPayment, fallback_ms: i64) -> i64 {
+    found.requested_at.as_deref()
+        .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+        .map(|ts| ts.timestamp_millis())
+        .unwrap_or(fallback_ms)
+}
+
+fn had_timeout(queued: &QueuedPayment, processor: &str) -> bool {
+    queued.history.iter()
+        .any(|attempt| attempt.processor.as_deref() == Some(processor) && attempt.outcome.is_some_and(|o| o.is_unresolved()))
+}
+
+// faz uma tentativa (com failover imediato quando seguro); nunca dorme esperando o backoff,
+// quem chamou agenda a próxima tentativa a partir de ProcessError::retry_after
+pub async fn process(
+    queued: &mut QueuedPayment,
+    store: Arc<dyn PaymentStore>,
+    client: Arc<Client>,
+    decision: ProcessorDecision,
+) -> Result<(), ProcessError> {
+    // o processador guarda o requestedAt da primeira vez que recebeu o pagamento,
+    // então toda nova tentativa manda o mesmo
+    if queued.requested_at == 0 {
+        queued.requested_at = Utc::now().timestamp_millis();
+    }
+    let payment = &queued.payment;
+    let id = payment.correlation_id.to_string();
+    let elapsed = elapsed_since(queued.enqueued_at).unwrap_or_default();
 
-    let timestamp = Utc::now();
-    let timestamp_str = timestamp.to_rfc3339_opts(SecondsFormat::Millis, true);
-    let timestamp_ms = timestamp.timestamp_millis() as f64;
+    // já contabilizado (ex: reprocessado após crash antes do ack)
+    if store.is_recorded(&id).await.map_err(|e| process_error(None, e))? {
+        return Ok(());
+    }
+
+    let preferred = match decision {
+        ProcessorDecision::PROCESSOR(processor) => processor,
+        ProcessorDecision::FAILING => return Err(process_error(None, "Nenhum processador disponível".to_string())),
+    };
+    // se o pagamento já foi enviado a um processador, ele só pode ir para esse mesmo
+    let mut processor = store.pin_processor(&id, &preferred).await
+        .map_err(|e| process_error(None, e))?;
+
+    let timestamp_ms = queued.requested_at;
+    let timestamp_str = DateTime::from_timestamp_millis(timestamp_ms)
+        .unwrap_or_else(Utc::now)
+        .to_rfc3339_opts(SecondsFormat::Millis, true);
     let payload = serde_json::json!({
         "correlationId": payment.correlation_id,
         "amount": payment.amount,
         "requestedAt" : timestamp_str
     });
-    let id = format!("{}", payment.correlation_id);
-    let mut is_failed = false;
-    if decision == ProcessorDecision::DEFAULT {
-        for _ in 0..5 {
-            let normal_request = payments_request(&client, PAYMENT_PROCESSOR_DEFAULT_URL.as_str().parse().unwrap(), &payload).await?;
-            let status = normal_request.status();
-            if status.is_success() {
-                store_summary(&mut conn, "default", &id, payment.amount, timestamp_ms).await?;
-                return Ok(());
+
+    loop {
+        let policy = retry_policy(&processor);
+        let breaker = circuit_breaker(&processor);
+
+        // tentativas desse processador já esgotadas: nem envia, passa direto para o próximo
+        let exhausted = queued.processor_attempts.get(&processor).copied().unwrap_or(0) >= policy.max_attempts;
+        // circuito aberto: nem envia, o pagamento não foi cobrado e pode ir para o fallback
+        let (outcome, reason) = if exhausted {
+            (None, "tentativas esgotadas nesse processador".to_string())
+        } else if !breaker.try_acquire() {
+            (None, "circuito aberto".to_string())
+        } else {
+            let attempts = queued.processor_attempts.entry(processor.clone()).or_insert(0);
+            *attempts += 1;
+            let timeout = processor_timeout(&processor).await;
+            let started = Instant::now();
+            let response = payments_request(&client, processor_url(&processor), &payload, timeout).await;
+            let (outcome, reason) = classify_response(response).await;
+            record_call(&processor, started.elapsed(), outcome);
+            record_outcome(&processor, outcome);
+            breaker.record(outcome);
+
+            match outcome {
+                ProcessorOutcome::Success => {
+                    store.record(&processor, &id, payment.amount, timestamp_ms).await
+                        .map_err(|e| process_error(Some(&processor), e))?;
+                    return Ok(());
+                }
+                // duplicado: o processador já tem o pagamento, conta para ele com o horário que ele guardou
+                ProcessorOutcome::Duplicate => {
+                    let stored_ms = match verify_payment(&client, processor_url(&processor), &id, timeout).await {
+                        PaymentVerification::Found(found) => found_timestamp(&found, timestamp_ms),
+                        _ => timestamp_ms,
+                    };
+                    store.record(&processor, &id, payment.amount, stored_ms).await
+                        .map_err(|e| process_error(Some(&processor), e))?;
+                    return Ok(());
+                }
+                // rejeição permanente: não repete e o pagamento continua fixado nesse processador
+                ProcessorOutcome::PermanentRejection => {
+                    return Err(outcome_error(&processor, outcome, reason, None));
+                }
+                // sem resposta (ou resposta quebrada): antes de repetir, pergunta se ele cobrou
+                ProcessorOutcome::Timeout | ProcessorOutcome::ResponseError => {
+                    let reason = match verify_payment(&client, processor_url(&processor), &id, timeout).await {
+                        PaymentVerification::Found(found) => {
+                            let found_ms = found_timestamp(&found, timestamp_ms);
+                            eprintln!("[{}] {} para {}: confirmado pelo processador", processor, outcome.as_str(), id);
+                            store.record(&processor, &id, payment.amount, found_ms).await
+                                .map_err(|e| process_error(Some(&processor), e))?;
+                            return Ok(());
+                        }
+                        PaymentVerification::NotFound => format!("{} (verificação: processador ainda não tem o pagamento)", reason),
+                        PaymentVerification::Unknown(e) => format!("{} (verificação: {})", reason, e),
+                    };
+                    // não encontrado logo depois de um timeout não prova nada, o processador pode ainda estar
+                    // processando: o pagamento continua fixado e repete no mesmo, que deduplica pelo correlationId
+                    let attempts = *queued.processor_attempts.get(&processor).unwrap_or(&0);
+                    let retry_after = policy.can_retry(outcome, attempts, elapsed).then(|| policy.delay(attempts));
+                    return Err(outcome_error(&processor, outcome, reason, retry_after));
+                }
+                ProcessorOutcome::TransientFailure | ProcessorOutcome::ConnectionError => {
+                    eprintln!("[{}] {} para {}: {}", processor, outcome.as_str(), id, reason);
+                }
+            }
+            (Some(outcome), reason)
+        };
+
+        let attempts = queued.processor_attempts.get(&processor).copied().unwrap_or(0);
+        let retry_after = match outcome {
+            _ if exhausted => None,
+            Some(outcome) => policy.can_retry(outcome, attempts, elapsed).then(|| policy.delay(attempts)),
+            None => Some(policy.delay(attempts.max(1))),
+        };
+
+        // um timeout anterior nesse processador nunca foi esclarecido: ele pode ter cobrado,
+        // então o pagamento continua fixado nele mesmo que esta tentativa tenha falhado de forma clara
+        if had_timeout(queued, &processor) {
+            return Err(ProcessError {
+                processor: Some(processor.clone()),
+                outcome,
+                reason,
+                retry_after,
+            });
+        }
+
+        // daqui pra baixo o processador com certeza não cobrou (erro, sem conexão ou circuito aberto):
+        // libera para o roteamento decidir de novo
+        store.release_processor(&id, &processor).await
+            .map_err(|e| process_error(Some(&processor), e))?;
+
+        // esse processador esgotou ou está com circuito aberto: tenta o próximo na prioridade agora, sem esperar
+        if retry_after.is_none() || outcome.is_none() {
+            // só fixa o próximo se ele ainda tiver tentativas, senão o pin ficaria para trás
+            let next_config = next_processor(&processor).filter(|next| {
+                queued.processor_attempts.get(&next.name).copied().unwrap_or(0) < retry_policy(&next.name).max_attempts
+            });
+            if let Some(next_config) = next_config {
+                let next = store.pin_processor(&id, &next_config.name).await
+                    .map_err(|e| process_error(Some(&processor), e))?;
+                if next != processor {
+                    processor = next;
+                    continue;
+                }
             }
-            tokio::time::sleep(Duration::from_millis(500)).await;
         }
-        is_failed = true;
+
+        return Err(ProcessError {
+            processor: Some(processor.clone()),
+            outcome,
+            reason,
+            retry_after,
+        });
     }
+}
 
-    if decision == ProcessorDecision::FALLBACK || is_failed {
-        let fallback_request = payments_request(&client, PAYMENT_PROCESSOR_FALLBACK_URL.as_str().parse().unwrap(), &payload).await?;
-        let status = fallback_request.status();
-        if status.is_success() {
-            store_summary(&mut conn, "fallback", &id, payment.amount, timestamp_ms).await?;
-            return Ok(());
+#[cfg(test)]
+mod tests {
+    use super::{process, INFRA_RETRY_DELAY};
+    use crate::domain::entities::{AttemptRecord, PaymentRecord, PostPayments, ProcessorDecision, ProcessorOutcome, QueuedPayment};
+    use crate::domain::money::Money;
+    use crate::domain::store::{PaymentStore, StoreResult, SummarySeries};
+    use crate::infrastructure::config::retry_policy;
+    use crate::infrastructure::MemoryPaymentStore;
+    use futures::future::BoxFuture;
+    use reqwest::Client;
+    use std::sync::{Arc, Mutex};
+
+    // store em memória que anota as chamadas de pin/release e pode falhar de propósito
+    #[derive(Default)]
+    struct FakeStore {
+        inner: MemoryPaymentStore,
+        calls: Mutex<Vec<String>>,
+        failing: bool,
+    }
+
+    impl FakeStore {
+        fn calls(&self) -> Vec<String> {
+            self.calls.lock().unwrap().clone()
+        }
+
+        fn check(&self) -> StoreResult<()> {
+            if self.failing { Err("store fora do ar".to_string()) } else { Ok(()) }
         }
     }
 
-    Err("Erro ao enviar todas as requisiçoes".to_string().into())
-}
+    impl PaymentStore for FakeStore {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn record<'a>(&'a self, processor: &'a str, id: &'a str, amount: Money, timestamp_ms: i64) -> BoxFuture<'a, StoreResult<bool>> {
+            self.calls.lock().unwrap().push(format!("record {}", processor));
+            self.inner.record(processor, id, amount, timestamp_ms)
+        }
 
+        fn is_recorded<'a>(&'a self, id: &'a str) -> BoxFuture<'a, StoreResult<bool>> {
+            match self.check() {
+                Ok(()) => self.inner.is_recorded(id),
+                Err(e) => Box::pin(async move { Err(e) }),
+            }
+        }
+
+        fn lookup<'a>(&'a self, id: &'a str) -> BoxFuture<'a, StoreResult<Option<PaymentRecord>>> {
+            self.inner.lookup(id)
+        }
+
+        fn query_range<'a>(&'a self, processor: &'a str, from: i64, to: i64) -> BoxFuture<'a, StoreResult<Vec<PaymentRecord>>> {
+            self.inner.query_range(processor, from, to)
+        }
+
+        fn summary<'a>(&'a self, processors: &'a [&'a str], from: i64, to: i64) -> BoxFuture<'a, StoreResult<Vec<(u64, Money)>>> {
+            self.inner.summary(processors, from, to)
+        }
+
+        fn series<'a>(&'a self, processors: &'a [&'a str], from: i64, to: i64, interval: i64) -> BoxFuture<'a, StoreResult<SummarySeries>> {
+            self.inner.series(processors, from, to, interval)
+        }
+
+        fn pin_processor<'a>(&'a self, id: &'a str, processor: &'a str) -> BoxFuture<'a, StoreResult<String>> {
+            self.calls.lock().unwrap().push(format!("pin {}", processor));
+            self.inner.pin_processor(id, processor)
+        }
+
+        fn release_processor<'a>(&'a self, id: &'a str, processor: &'a str) -> BoxFuture<'a, StoreResult<()>> {
+            self.calls.lock().unwrap().push(format!("release {}", processor));
+            self.inner.release_processor(id, processor)
+        }
+
+        fn purge<'a>(&'a self, processors: &'a [&'a str]) -> BoxFuture<'a, StoreResult<()>> {
+            self.inner.purge(processors)
+        }
+    }
+
+    fn queued(id: &str) -> QueuedPayment {
+        QueuedPayment {
+            payment: PostPayments { correlation_id: id.to_string(), amount: Money::from_cents(1990) },
+            attempts: 0,
+            processor_attempts: Default::default(),
+            enqueued_at: 0,
+            requested_at: 0,
+            history: Vec::new(),
+        }
+    }
+
+    // sem tentativas sobrando o process não chega a chamar o processador
+    fn exhaust(queued: &mut QueuedPayment, processor: &str) {
+        queued.processor_attempts.insert(processor.to_string(), retry_policy(processor).max_attempts);
+    }
+
+    fn default_processor() -> ProcessorDecision {
+        ProcessorDecision::PROCESSOR("default".to_string())
+    }
+
+    #[tokio::test]
+    async fn already_recorded_payment_is_done() {
+        let store = Arc::new(FakeStore::default());
+        store.inner.record("default", "a", Money::from_cents(1990), 1_000).await.unwrap();
+
+        let mut queued = queued("a");
+        let result = process(&mut queued, store.clone(), Arc::new(Client::new()), ProcessorDecision::FAILING).await;
+        assert!(result.is_ok());
+        assert!(store.calls().is_empty());
+        assert_ne!(queued.requested_at, 0);
+    }
+
+    #[tokio::test]
+    async fn no_processor_available_retries_without_pinning() {
+        let store = Arc::new(FakeStore::default());
+        let mut queued = queued("a");
+        let err = process(&mut queued, store.clone(), Arc::new(Client::new()), ProcessorDecision::FAILING).await.unwrap_err();
+        assert_eq!(err.processor, None);
+        assert_eq!(err.retry_after, Some(INFRA_RETRY_DELAY));
+        assert!(store.calls().is_empty());
+    }
+
+    #[tokio::test]
+    async fn store_error_is_retried_soon() {
+        let store = Arc::new(FakeStore { failing: true, ..Default::default() });
+        let mut queued = queued("a");
+        let err = process(&mut queued, store.clone(), Arc::new(Client::new()), default_processor()).await.unwrap_err();
+        assert_eq!(err.retry_after, Some(INFRA_RETRY_DELAY));
+        assert!(err.reason.contains("store fora do ar"));
+    }
+
+    #[tokio::test]
+    async fn exhausted_processors_give_up_and_release_the_pin() {
+        let store = Arc::new(FakeStore::default());
+        let mut queued = queued("a");
+        exhaust(&mut queued, "default");
+        exhaust(&mut queued, "fallback");
+
+        let err = process(&mut queued, store.clone(), Arc::new(Client::new()), default_processor()).await.unwrap_err();
+        assert_eq!(err.processor.as_deref(), Some("default"));
+        assert_eq!(err.retry_after, None);
+        assert_eq!(store.calls(), ["pin default", "release default"]);
+        // nada ficou fixado
+        assert_eq!(store.inner.pin_processor("a", "fallback").await.unwrap(), "fallback");
+    }
+
+    #[tokio::test]
+    async fn unresolved_timeout_keeps_the_pin() {
+        let store = Arc::new(FakeStore::default());
+        let mut queued = queued("a");
+        exhaust(&mut queued, "default");
+        queued.history.push(AttemptRecord {
+            processor: Some("default".to_string()),
+            outcome: Some(ProcessorOutcome::Timeout),
+            reason: "timeout".to_string(),
+            at: 1_000,
+        });
+
+        let err = process(&mut queued, store.clone(), Arc::new(Client::new()), default_processor()).await.unwrap_err();
+        assert_eq!(err.processor.as_deref(), Some("default"));
+        assert_eq!(err.retry_after, None);
+        assert_eq!(store.calls(), ["pin default"]);
+        assert_eq!(store.inner.pin_processor("a", "fallback").await.unwrap(), "default");
+    }
+}
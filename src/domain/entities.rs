@@ -1,10 +1,28 @@
-use redis::aio::ConnectionManager;
+use crate::domain::money::{deserialize_positive, deserialize_rounded, Money};
+use crate::domain::queue::PaymentQueue;
+use crate::domain::store::PaymentStore;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::sync::Arc;
-use axum::body::Bytes;
-use tokio::sync::mpsc::{UnboundedSender};
+use std::time::Duration;
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ProcessorConfig {
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub priority: u32,
+    #[serde(default)]
+    pub fee: f64,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 pub struct HealthResponse {
     pub failing : bool,
     #[serde(rename = "minResponseTime")]
@@ -12,50 +30,309 @@ pub struct HealthResponse {
     pub failing_since: Option<u64>,
 }
 
-#[derive(Deserialize, Serialize, Debug,Clone)]
+// serializa como {"default": {...}, "fallback": {...}, ...}
+#[derive(Deserialize, Serialize, Debug,Clone, Default)]
 pub struct HealthStatusAll {
-    pub default: HealthResponse,
-    pub fallback: HealthResponse,
+    #[serde(flatten)]
+    pub processors: BTreeMap<String, HealthResponse>,
+    // quando o líder fez o último health check com sucesso (0 = nunca)
+    #[serde(rename = "updatedAt", default)]
+    pub updated_at: u64,
+}
+
+impl HealthStatusAll {
+    pub fn get(&self, processor: &str) -> Option<&HealthResponse> {
+        self.processors.get(processor)
+    }
+
+    pub fn is_failing(&self, processor: &str) -> bool {
+        self.get(processor).map(|h| h.failing).unwrap_or(true)
+    }
+
+    pub fn is_stale(&self, now_ms: u64, max_age: Duration) -> bool {
+        now_ms.saturating_sub(self.updated_at) > max_age.as_millis() as u64
+    }
 }
 
 #[derive(Clone)]
 pub struct AppState {
-    pub redis: Arc<ConnectionManager>,
-    pub sender: UnboundedSender<Bytes>
+    pub store: Arc<dyn PaymentStore>,
+    pub queue: Arc<dyn PaymentQueue>,
 }
 
 #[derive(Deserialize, Serialize, Debug,Clone)]
 pub struct PaymentsSummaryFilter {
     pub from: Option<String>,
     pub to: Option<String>,
+    // [] (padrão), [), (] ou ()
+    pub bounds: Option<String>,
+    // série temporal: second|minute|hour|day, ou interval=30s, 5m...
+    pub group_by: Option<String>,
+    pub interval: Option<String>,
+}
+
+// consulta dos pagamentos contabilizados de um processador, mesmo formato de intervalo do summary
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct PaymentsQueryFilter {
+    pub processor: String,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub bounds: Option<String>,
+}
+
+// corpo das respostas de erro da API
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ApiError {
+    pub error: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
 }
 
 #[derive(Deserialize, Serialize, Debug)]
 pub struct SummaryData {
     #[serde(rename = "totalRequests")]
     pub total_requests: i64,
-    #[serde(rename = "totalAmount")]
-    pub total_amount: f64,
+    // também lê o summary dos processadores, que pode ter taxa com mais de 2 casas
+    #[serde(rename = "totalAmount", deserialize_with = "deserialize_rounded")]
+    pub total_amount: Money,
+    #[serde(rename = "totalFee", default, deserialize_with = "deserialize_rounded")]
+    pub total_fee: Money,
+    #[serde(rename = "netAmount", default, deserialize_with = "deserialize_rounded")]
+    pub net_amount: Money,
 }
 
+// um campo por processador configurado, mantém {"default": ..., "fallback": ...}
 #[derive(Deserialize, Serialize, Debug)]
 pub struct PaymentsSummary {
-    pub default: SummaryData,
-    pub fallback: SummaryData,
+    #[serde(flatten)]
+    pub processors: BTreeMap<String, SummaryData>,
+    // só com group_by/interval: pontos em ordem de tempo, apenas os que tiveram pagamentos
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub series: Option<Vec<SummaryBucket>>,
 }
 
+// um ponto da série: início do intervalo (RFC3339, UTC) e os totais de cada processador nele
 #[derive(Deserialize, Serialize, Debug)]
+pub struct SummaryBucket {
+    pub from: String,
+    #[serde(flatten)]
+    pub processors: BTreeMap<String, SummaryData>,
+}
+
+// pagamento já contabilizado: em qual processador, quanto e quando (ms)
+#[derive(Deserialize, Serialize, Debug, Clone, PartialEq)]
+pub struct PaymentRecord {
+    #[serde(rename = "correlationId")]
+    pub correlation_id: String,
+    pub processor: String,
+    pub amount: Money,
+    #[serde(rename = "requestedAt")]
+    pub requested_at: i64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct PostPayments {
     #[serde(rename = "correlationId")]
     pub correlation_id: String,
-    pub amount: f64,
+    #[serde(deserialize_with = "deserialize_positive")]
+    pub amount: Money,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct AttemptRecord {
+    pub processor: Option<String>,
+    pub outcome: Option<ProcessorOutcome>,
+    pub reason: String,
+    // ms desde epoch
+    pub at: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct QueuedPayment {
+    #[serde(flatten)]
+    pub payment: PostPayments,
+    #[serde(default)]
+    pub attempts: u32,
+    #[serde(rename = "processorAttempts", default)]
+    pub processor_attempts: BTreeMap<String, u32>,
+    // ms desde epoch em que o pagamento foi aceito (ou reenviado pelo replay)
+    #[serde(rename = "enqueuedAt", default)]
+    pub enqueued_at: u64,
+    // requestedAt enviado na primeira tentativa (ms), repetido em todas as outras
+    #[serde(rename = "requestedAt", default)]
+    pub requested_at: i64,
+    #[serde(default)]
+    pub history: Vec<AttemptRecord>,
+}
+
+// replay: volta para a fila com as tentativas zeradas e o prazo recomeçando em now_ms,
+// mas mantém o histórico e o requestedAt
+impl QueuedPayment {
+    pub fn replay(entry: DeadLetter, now_ms: u64) -> QueuedPayment {
+        QueuedPayment {
+            payment: entry.payment,
+            attempts: 0,
+            processor_attempts: Default::default(),
+            enqueued_at: now_ms,
+            requested_at: entry.requested_at,
+            history: entry.history,
+        }
+    }
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct DeadLetter {
+    pub payment: PostPayments,
+    pub reason: String,
+    pub attempts: u32,
+    #[serde(rename = "lastProcessor")]
+    pub last_processor: Option<String>,
+    #[serde(rename = "failedAt")]
+    pub failed_at: String,
+    // mantido para o replay mandar o mesmo requestedAt das tentativas anteriores
+    #[serde(rename = "requestedAt", default)]
+    pub requested_at: i64,
+    #[serde(default)]
+    pub history: Vec<AttemptRecord>,
+}
+
+// estatísticas observadas nas chamadas reais; calls/errors são da janela deslizante
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct ProcessorStats {
+    pub calls: u64,
+    pub errors: u64,
+    #[serde(rename = "ewmaMs")]
+    pub ewma_ms: f64,
+    #[serde(rename = "p50Ms")]
+    pub p50_ms: u64,
+    #[serde(rename = "p95Ms")]
+    pub p95_ms: u64,
+    #[serde(rename = "p99Ms")]
+    pub p99_ms: u64,
+}
+
+impl ProcessorStats {
+    pub fn error_rate(&self) -> f64 {
+        if self.calls == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.calls as f64
+        }
+    }
+}
+
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct RoutingStats {
+    pub processors: BTreeMap<String, ProcessorStats>,
+}
+
+impl RoutingStats {
+    pub fn get(&self, processor: &str) -> ProcessorStats {
+        self.processors.get(processor).cloned().unwrap_or_default()
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProcessorOutcome {
+    Success,
+    // o processador já tinha esse correlationId
+    Duplicate,
+    // 4xx de validação, tentar de novo não muda nada
+    PermanentRejection,
+    // 5xx/429/408, o processador recusou e não cobrou
+    TransientFailure,
+    // sem resposta depois de enviar: pode ou não ter sido cobrado
+    Timeout,
+    // não conectou, a requisição nem saiu
+    ConnectionError,
+    // enviou mas a resposta veio quebrada (corpo, redirect...): também pode ter sido cobrado
+    ResponseError,
+}
+
+impl ProcessorOutcome {
+    pub const ALL: [ProcessorOutcome; 7] = [
+        ProcessorOutcome::Success,
+        ProcessorOutcome::Duplicate,
+        ProcessorOutcome::PermanentRejection,
+        ProcessorOutcome::TransientFailure,
+        ProcessorOutcome::Timeout,
+        ProcessorOutcome::ConnectionError,
+        ProcessorOutcome::ResponseError,
+    ];
+
+    pub fn parse(name: &str) -> Option<ProcessorOutcome> {
+        ProcessorOutcome::ALL.iter().copied().find(|o| o.as_str() == name)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProcessorOutcome::Success => "success",
+            ProcessorOutcome::Duplicate => "duplicate",
+            ProcessorOutcome::PermanentRejection => "permanent_rejection",
+            ProcessorOutcome::TransientFailure => "transient_failure",
+            ProcessorOutcome::Timeout => "timeout",
+            ProcessorOutcome::ConnectionError => "connection_error",
+            ProcessorOutcome::ResponseError => "response_error",
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self,
+            ProcessorOutcome::TransientFailure | ProcessorOutcome::Timeout | ProcessorOutcome::ConnectionError | ProcessorOutcome::ResponseError
+        )
+    }
+
+    // a requisição saiu e não veio resposta clara: antes de mandar para outro, precisa perguntar se cobrou
+    pub fn is_unresolved(&self) -> bool {
+        matches!(self, ProcessorOutcome::Timeout | ProcessorOutcome::ResponseError)
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct ProcessorPayment {
+    #[serde(rename = "correlationId")]
+    pub correlation_id: String,
+    #[serde(deserialize_with = "deserialize_rounded")]
+    pub amount: Money,
+    #[serde(rename = "requestedAt")]
+    pub requested_at: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum PaymentVerification {
+    Found(ProcessorPayment),
+    NotFound,
+    Unknown(String),
+}
+
+#[derive(Debug)]
+pub struct ProcessError {
+    pub processor: Option<String>,
+    pub outcome: Option<ProcessorOutcome>,
+    pub reason: String,
+    // quando tentar de novo; None = desistir e mandar para a fila de falhas
+    pub retry_after: Option<std::time::Duration>,
+}
+
+impl std::fmt::Display for ProcessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(processor) = &self.processor {
+            write!(f, "[{}] ", processor)?;
+        }
+        if let Some(outcome) = &self.outcome {
+            write!(f, "{}: ", outcome.as_str())?;
+        }
+        write!(f, "{}", self.reason)
+    }
+}
+
+impl std::error::Error for ProcessError {}
+
 pub type AnyError = Box<dyn std::error::Error + Send + Sync>;
 
-#[derive(Deserialize, Serialize,Debug, PartialEq)]
+#[derive(Deserialize, Serialize,Debug, PartialEq, Clone)]
 pub enum ProcessorDecision {
-    DEFAULT,
-    FALLBACK,
+    PROCESSOR(String),
     FAILING,
 }
@@ -1 +1,6 @@
 pub mod entities;
+pub mod money;
+pub mod queue;
+pub mod retry;
+pub mod store;
+pub mod time_range;
@@ -0,0 +1,193 @@
+use redis::{FromRedisValue, RedisResult, RedisWrite, ToRedisArgs, Value};
+use serde::de::Error as DeError;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Sub};
+
+// valor em centavos; no JSON continua um número com até 2 casas (19.9 -> 1990 -> 19.9)
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Money(i64);
+
+impl Money {
+    pub const ZERO: Money = Money(0);
+    // 2^53 centavos: acima disso o f64 não representa todo centavo e as somas no redis podem estourar
+    pub const MAX_CENTS: i64 = 1 << 53;
+
+    pub fn from_cents(cents: i64) -> Money {
+        Money(cents)
+    }
+
+    pub fn cents(&self) -> i64 {
+        self.0
+    }
+
+    // só aceita valores com no máximo 2 casas decimais e até MAX_CENTS
+    pub fn from_f64_exact(value: f64) -> Option<Money> {
+        if !value.is_finite() {
+            return None;
+        }
+        let scaled = value * 100.0;
+        let cents = scaled.round();
+        if (scaled - cents).abs() > 1e-6 || cents.abs() > Money::MAX_CENTS as f64 {
+            return None;
+        }
+        Some(Money(cents as i64))
+    }
+
+    // arredonda para o centavo mais próximo (valores que vêm de fora, ex: totais dos processadores)
+    pub fn from_f64_rounded(value: f64) -> Money {
+        Money((value * 100.0).round() as i64)
+    }
+
+    // taxa em fração (0.05 = 5%), calculada em inteiros e arredondada para o centavo (meio para cima)
+    pub fn fee(&self, rate: f64) -> Money {
+        let basis_points = (rate * 10_000.0).round() as i128;
+        let fee = (self.0 as i128 * basis_points + 5_000).div_euclid(10_000);
+        Money(fee as i64)
+    }
+
+    // exato para qualquer valor em centavos até 2^53
+    pub fn as_f64(&self) -> f64 {
+        self.0 as f64 / 100.0
+    }
+}
+
+impl fmt::Display for Money {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let cents = self.0.unsigned_abs();
+        write!(f, "{}{}.{:02}", sign, cents / 100, cents % 100)
+    }
+}
+
+impl Add for Money {
+    type Output = Money;
+
+    fn add(self, other: Money) -> Money {
+        Money(self.0 + other.0)
+    }
+}
+
+impl AddAssign for Money {
+    fn add_assign(&mut self, other: Money) {
+        self.0 += other.0;
+    }
+}
+
+impl Sub for Money {
+    type Output = Money;
+
+    fn sub(self, other: Money) -> Money {
+        Money(self.0 - other.0)
+    }
+}
+
+impl Sum for Money {
+    fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+        iter.fold(Money::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Money> for Money {
+    fn sum<I: Iterator<Item = &'a Money>>(iter: I) -> Money {
+        iter.copied().sum()
+    }
+}
+
+// o f64 de n/100 sempre serializa com a menor representação, que é o próprio decimal de 2 casas
+impl Serialize for Money {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.as_f64())
+    }
+}
+
+impl<'de> Deserialize<'de> for Money {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Money, D::Error> {
+        let value = f64::deserialize(deserializer)?;
+        Money::from_f64_exact(value)
+            .ok_or_else(|| D::Error::custom(format!("valor monetário inválido: {} (máximo 2 casas decimais)", value)))
+    }
+}
+
+// para campos que vêm de fora com mais casas (ex: totalFee do processador)
+pub fn deserialize_rounded<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Money, D::Error> {
+    f64::deserialize(deserializer).map(Money::from_f64_rounded)
+}
+
+// valor de um pagamento recebido: além do formato, precisa ser positivo
+pub fn deserialize_positive<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Money, D::Error> {
+    let amount = Money::deserialize(deserializer)?;
+    if amount <= Money::ZERO {
+        return Err(D::Error::custom(format!("valor monetário deve ser positivo: {}", amount)));
+    }
+    Ok(amount)
+}
+
+// no redis fica o inteiro em centavos
+impl ToRedisArgs for Money {
+    fn write_redis_args<W: ?Sized + RedisWrite>(&self, out: &mut W) {
+        self.0.write_redis_args(out)
+    }
+}
+
+impl FromRedisValue for Money {
+    fn from_redis_value(v: &Value) -> RedisResult<Money> {
+        i64::from_redis_value(v).map(Money)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Money;
+
+    fn parse(json: &str) -> Result<Money, serde_json::Error> {
+        serde_json::from_str(json)
+    }
+
+    #[test]
+    fn json_round_trip_keeps_two_decimals() {
+        for (json, cents) in [("19.9", 1990), ("0.1", 10), ("0.2", 20), ("1000", 100_000), ("0.07", 7)] {
+            let money = parse(json).unwrap();
+            assert_eq!(money.cents(), cents, "{}", json);
+            assert_eq!(serde_json::to_string(&money).unwrap(), serde_json::to_string(&money.as_f64()).unwrap());
+            assert_eq!(parse(&serde_json::to_string(&money).unwrap()).unwrap(), money);
+        }
+        assert_eq!(serde_json::to_string(&Money::from_cents(1990)).unwrap(), "19.9");
+    }
+
+    #[test]
+    fn rejects_more_than_two_decimals() {
+        assert!(parse("19.999").is_err());
+        assert!(parse("0.001").is_err());
+        assert!(parse("0.105").is_err());
+    }
+
+    #[test]
+    fn rejects_amounts_above_max_cents() {
+        assert!(parse("92233720368547758.07").is_err());
+        assert!(parse("1e300").is_err());
+        assert_eq!(Money::from_f64_exact(Money::MAX_CENTS as f64 / 100.0), Some(Money::from_cents(Money::MAX_CENTS)));
+        assert_eq!(Money::from_f64_exact(Money::MAX_CENTS as f64 / 100.0 * 2.0), None);
+    }
+
+    #[test]
+    fn sums_are_exact() {
+        let total: Money = [parse("0.1").unwrap(), parse("0.2").unwrap()].iter().sum();
+        assert_eq!(total, Money::from_cents(30));
+        assert_eq!(serde_json::to_string(&total).unwrap(), "0.3");
+
+        let total: Money = vec![Money::from_cents(1990); 1000].into_iter().sum();
+        assert_eq!(total.to_string(), "19900.00");
+    }
+
+    #[test]
+    fn fee_rounds_half_up_to_the_cent() {
+        assert_eq!(Money::from_cents(1990).fee(0.05), Money::from_cents(100)); // 99.5
+        assert_eq!(Money::from_cents(1990).fee(0.15), Money::from_cents(299)); // 298.5
+        assert_eq!(Money::from_cents(1989).fee(0.05), Money::from_cents(99)); // 99.45
+        assert_eq!(Money::from_cents(10).fee(0.05), Money::from_cents(1)); // 0.5
+        assert_eq!(Money::from_cents(9).fee(0.05), Money::ZERO); // 0.45
+        assert_eq!(Money::ZERO.fee(0.15), Money::ZERO);
+    }
+}
@@ -0,0 +1,31 @@
+use crate::domain::entities::DeadLetter;
+use crate::domain::store::StoreResult;
+use futures::future::BoxFuture;
+use std::time::Duration;
+
+// fila de pagamentos aceitos: o item pego por um worker fica "em processamento" até
+// ack, nova tentativa agendada ou dead letter
+pub trait PaymentQueue: Send + Sync {
+    fn name(&self) -> &'static str;
+    // handle para um worker (no redis o BLMOVE bloqueia a conexão, então cada worker tem a sua)
+    fn for_worker(&self) -> BoxFuture<'_, StoreResult<Box<dyn PaymentQueue>>>;
+    // o correlationId só entra uma vez: false se já tinha sido aceito
+    fn enqueue<'a>(&'a self, correlation_id: &'a str, payload: &'a [u8]) -> BoxFuture<'a, StoreResult<bool>>;
+    // esquece os correlationIds aceitos, para um POST repetido depois do purge ser processado
+    fn purge_accepted(&self) -> BoxFuture<'_, StoreResult<()>>;
+    fn claim(&self, timeout: Duration) -> BoxFuture<'_, StoreResult<Option<Vec<u8>>>>;
+    fn ack<'a>(&'a self, claimed: &'a [u8]) -> BoxFuture<'a, StoreResult<()>>;
+    fn in_flight(&self) -> BoxFuture<'_, StoreResult<u64>>;
+    // devolve para a fila o que ficou em processamento (crash/restart)
+    fn recover_in_flight(&self) -> BoxFuture<'_, StoreResult<usize>>;
+    // tira de processamento e guarda `payload` para voltar à fila em due_ms
+    fn schedule_retry<'a>(&'a self, claimed: &'a [u8], payload: &'a [u8], due_ms: u64) -> BoxFuture<'a, StoreResult<()>>;
+    fn promote_due(&self, now_ms: u64, limit: usize) -> BoxFuture<'_, StoreResult<usize>>;
+    fn dead_letter<'a>(&'a self, claimed: &'a [u8], entry: &'a DeadLetter) -> BoxFuture<'a, StoreResult<()>>;
+    fn list_dead_letters(&self) -> BoxFuture<'_, StoreResult<Vec<DeadLetter>>>;
+    fn get_dead_letter<'a>(&'a self, correlation_id: &'a str) -> BoxFuture<'a, StoreResult<Option<DeadLetter>>>;
+    // só quem conseguir tirar a entrada das falhas recoloca na fila
+    fn replay_dead_letter(&self, entry: DeadLetter) -> BoxFuture<'_, StoreResult<bool>>;
+    fn discard_dead_letter<'a>(&'a self, correlation_id: &'a str) -> BoxFuture<'a, StoreResult<bool>>;
+    fn discard_all_dead_letters(&self) -> BoxFuture<'_, StoreResult<usize>>;
+}
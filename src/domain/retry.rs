@@ -0,0 +1,87 @@
+use crate::domain::entities::ProcessorOutcome;
+use rand::Rng;
+use std::time::Duration;
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    // fração do atraso sorteada para mais ou para menos (0.2 = ±20%)
+    pub jitter: f64,
+    // tempo máximo desde que o pagamento entrou na fila
+    pub deadline: Duration,
+    pub retryable: Vec<ProcessorOutcome>,
+}
+
+impl RetryPolicy {
+    pub fn can_retry(&self, outcome: ProcessorOutcome, attempts: u32, elapsed: Duration) -> bool {
+        self.retryable.contains(&outcome) && attempts < self.max_attempts && elapsed < self.deadline
+    }
+
+    // backoff exponencial a partir da tentativa que acabou de falhar (1, 2, 3...)
+    pub fn delay(&self, attempts: u32) -> Duration {
+        let exponent = attempts.saturating_sub(1).min(16);
+        let delay = self.base_delay.saturating_mul(1 << exponent).min(self.max_delay);
+        if self.jitter <= 0.0 {
+            return delay;
+        }
+        let factor = rand::rng().random_range((1.0 - self.jitter).max(0.0)..=(1.0 + self.jitter));
+        delay.mul_f64(factor).min(self.max_delay)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetryPolicy;
+    use crate::domain::entities::ProcessorOutcome::{self, ConnectionError, PermanentRejection, Timeout, TransientFailure};
+    use std::time::Duration;
+
+    fn policy(jitter: f64) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 5,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(1000),
+            jitter,
+            deadline: Duration::from_secs(10),
+            retryable: vec![TransientFailure, Timeout],
+        }
+    }
+
+    #[test]
+    fn delay_doubles_per_attempt_up_to_the_cap() {
+        let policy = policy(0.0);
+        let delays: Vec<u64> = (0..=6).map(|attempts| policy.delay(attempts).as_millis() as u64).collect();
+        assert_eq!(delays, [100, 100, 200, 400, 800, 1000, 1000]);
+        // expoente limitado, não estoura com muitas tentativas
+        assert_eq!(policy.delay(u32::MAX), Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds_and_under_the_cap() {
+        let policy = policy(0.5);
+        for _ in 0..200 {
+            let delay = policy.delay(2).as_millis() as u64;
+            assert!((100..=300).contains(&delay), "{}", delay);
+            assert!(policy.delay(5) <= policy.max_delay);
+        }
+    }
+
+    #[test]
+    fn retries_stop_at_max_attempts_and_deadline() {
+        let policy = policy(0.0);
+        assert!(policy.can_retry(TransientFailure, 4, Duration::from_secs(9)));
+        assert!(!policy.can_retry(TransientFailure, 5, Duration::ZERO));
+        assert!(!policy.can_retry(TransientFailure, 0, Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn only_retryable_outcomes_are_retried() {
+        let policy = policy(0.0);
+        let retried: Vec<ProcessorOutcome> = [TransientFailure, Timeout, ConnectionError, PermanentRejection]
+            .into_iter()
+            .filter(|outcome| policy.can_retry(*outcome, 0, Duration::ZERO))
+            .collect();
+        assert_eq!(retried, [TransientFailure, Timeout]);
+    }
+}
@@ -0,0 +1,30 @@
+use crate::domain::entities::PaymentRecord;
+use crate::domain::money::Money;
+use futures::future::BoxFuture;
+use std::collections::BTreeMap;
+
+pub type StoreResult<T> = Result<T, String>;
+
+// quantidade e soma por processador em cada intervalo, chave = início do intervalo em ms
+pub type SummarySeries = BTreeMap<i64, Vec<(u64, Money)>>;
+
+// onde ficam os pagamentos contabilizados; intervalos sempre em ms, inclusivos nas duas pontas
+pub trait PaymentStore: Send + Sync {
+    fn name(&self) -> &'static str;
+    // um correlationId só conta uma vez, em qualquer processador: false se já estava contabilizado
+    fn record<'a>(&'a self, processor: &'a str, id: &'a str, amount: Money, timestamp_ms: i64) -> BoxFuture<'a, StoreResult<bool>>;
+    fn is_recorded<'a>(&'a self, id: &'a str) -> BoxFuture<'a, StoreResult<bool>>;
+    fn lookup<'a>(&'a self, id: &'a str) -> BoxFuture<'a, StoreResult<Option<PaymentRecord>>>;
+    // pagamentos de um processador no intervalo, em ordem de horário
+    fn query_range<'a>(&'a self, processor: &'a str, from: i64, to: i64) -> BoxFuture<'a, StoreResult<Vec<PaymentRecord>>>;
+    // quantidade e soma de cada processador, na mesma ordem de `processors`
+    fn summary<'a>(&'a self, processors: &'a [&'a str], from: i64, to: i64) -> BoxFuture<'a, StoreResult<Vec<(u64, Money)>>>;
+    // o mesmo por intervalos de `interval` ms alinhados ao epoch, só os que tiveram pagamentos
+    fn series<'a>(&'a self, processors: &'a [&'a str], from: i64, to: i64, interval: i64) -> BoxFuture<'a, StoreResult<SummarySeries>>;
+    // fixa o pagamento num processador: devolve o já fixado ou fixa o informado
+    fn pin_processor<'a>(&'a self, id: &'a str, processor: &'a str) -> BoxFuture<'a, StoreResult<String>>;
+    // só libera se ainda estiver fixado nesse processador
+    fn release_processor<'a>(&'a self, id: &'a str, processor: &'a str) -> BoxFuture<'a, StoreResult<()>>;
+    // apaga os pagamentos contabilizados desses processadores e os que estão fixados
+    fn purge<'a>(&'a self, processors: &'a [&'a str]) -> BoxFuture<'a, StoreResult<()>>;
+}
@@ -0,0 +1,338 @@
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime};
+
+// abaixo disso um epoch numérico é lido como segundos, acima como milissegundos
+// (1e11 s cai no ano 5138, 1e11 ms ainda é 1973)
+const EPOCH_SECONDS_LIMIT: i64 = 100_000_000_000;
+const DAY_MS: i64 = 86_400_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    Inclusive,
+    Exclusive,
+}
+
+// valor informado no filtro: um instante vira [ms, ms + 1), uma data sem hora vira o dia inteiro
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Period {
+    pub start: i64,
+    pub end: i64,
+}
+
+impl Period {
+    // só instantes que o chrono representa (±262 mil anos), assim ms + 1 e afins nunca estouram
+    fn instant(ms: i64) -> Result<Period, String> {
+        if DateTime::from_timestamp_millis(ms).is_none() {
+            return Err(format!("data fora do intervalo suportado: {} ms", ms));
+        }
+        Ok(Period { start: ms, end: ms + 1 })
+    }
+}
+
+#[derive(Debug)]
+pub struct RangeError {
+    pub field: Option<&'static str>,
+    pub message: String,
+}
+
+impl RangeError {
+    fn new(field: Option<&'static str>, message: String) -> RangeError {
+        RangeError { field, message }
+    }
+}
+
+// intervalo resolvido em ms, sempre inclusivo nas duas pontas (pode ficar vazio: from > to)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeRange {
+    pub from: i64,
+    pub to: i64,
+}
+
+impl TimeRange {
+    // from/to ausentes deixam o intervalo aberto; bounds segue a notação de intervalo: [] (padrão), [), (], ()
+    pub fn resolve(from: Option<&str>, to: Option<&str>, bounds: Option<&str>, now_ms: i64, open_end: i64) -> Result<TimeRange, RangeError> {
+        let (from_bound, to_bound) = parse_bounds(bounds).map_err(|e| RangeError::new(Some("bounds"), e))?;
+        let from = parse_optional(from, now_ms).map_err(|e| RangeError::new(Some("from"), e))?;
+        let to = parse_optional(to, now_ms).map_err(|e| RangeError::new(Some("to"), e))?;
+
+        let both = from.is_some() && to.is_some();
+        let from = match (from, from_bound) {
+            (None, _) => 0,
+            (Some(period), Bound::Inclusive) => period.start,
+            (Some(period), Bound::Exclusive) => period.end,
+        };
+        let to = match (to, to_bound) {
+            (None, _) => open_end,
+            (Some(period), Bound::Inclusive) => period.end - 1,
+            (Some(period), Bound::Exclusive) => period.start - 1,
+        };
+        // compara já com os bounds aplicados: to=2025-07-15 cobre o dia todo, então from às 12h do mesmo dia vale
+        if both && from > to {
+            return Err(RangeError::new(None, "from deve ser menor ou igual a to (com os bounds aplicados)".to_string()));
+        }
+        Ok(TimeRange { from, to })
+    }
+}
+
+fn parse_optional(value: Option<&str>, now_ms: i64) -> Result<Option<Period>, String> {
+    match value.map(str::trim).filter(|v| !v.is_empty()) {
+        None => Ok(None),
+        Some(value) => parse_time(value, now_ms).map(Some),
+    }
+}
+
+pub fn parse_bounds(value: Option<&str>) -> Result<(Bound, Bound), String> {
+    let value = match value.map(str::trim).filter(|v| !v.is_empty()) {
+        None => return Ok((Bound::Inclusive, Bound::Inclusive)),
+        Some(value) => value,
+    };
+    let mut chars = value.chars();
+    let bounds = match (chars.next(), chars.next(), chars.next()) {
+        (Some(open), Some(close), None) => {
+            let from = match open {
+                '[' => Bound::Inclusive,
+                '(' => Bound::Exclusive,
+                _ => return Err(format!("bounds inválido: {:?} (use [], [), (] ou ())", value)),
+            };
+            let to = match close {
+                ']' => Bound::Inclusive,
+                ')' => Bound::Exclusive,
+                _ => return Err(format!("bounds inválido: {:?} (use [], [), (] ou ())", value)),
+            };
+            (from, to)
+        }
+        _ => return Err(format!("bounds inválido: {:?} (use [], [), (] ou ())", value)),
+    };
+    Ok(bounds)
+}
+
+// aceita: epoch em segundos ou ms, RFC3339 com qualquer offset, data/hora sem fuso (UTC),
+// data sem hora (o dia inteiro, UTC) e expressões relativas como now, now-5m, now+1h
+pub fn parse_time(value: &str, now_ms: i64) -> Result<Period, String> {
+    if let Some(relative) = value.strip_prefix("now") {
+        return parse_relative(relative, now_ms).and_then(Period::instant);
+    }
+    if let Ok(epoch) = value.parse::<i64>() {
+        let ms = if epoch.unsigned_abs() < EPOCH_SECONDS_LIMIT as u64 { epoch * 1000 } else { epoch };
+        return Period::instant(ms);
+    }
+    if let Some(dt) = parse_rfc3339(value) {
+        return Period::instant(dt.timestamp_millis());
+    }
+    for format in ["%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M:%S%.f"] {
+        if let Ok(naive) = NaiveDateTime::parse_from_str(value, format) {
+            return Period::instant(naive.and_utc().timestamp_millis());
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        let start = date.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp_millis();
+        return Ok(Period { start, end: start + DAY_MS });
+    }
+    Err(format!(
+        "data inválida: {:?} (use RFC3339, YYYY-MM-DD, epoch em segundos/ms ou now-5m)",
+        value
+    ))
+}
+
+// um "+" sem encode na query string chega como espaço: "...T12:00:00 02:00" é "...T12:00:00+02:00"
+fn parse_rfc3339(value: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(value).ok().or_else(|| {
+        let (datetime, offset) = value.rsplit_once(' ')?;
+        DateTime::parse_from_rfc3339(&format!("{}+{}", datetime, offset)).ok()
+    })
+}
+
+// "", "-5m", "+1h", "-30s", "-500ms", "-2d", "-1w" ("+" sem encode chega como espaço)
+fn parse_relative(offset: &str, now_ms: i64) -> Result<i64, String> {
+    if offset.is_empty() {
+        return Ok(now_ms);
+    }
+    let invalid = || format!("expressão relativa inválida: now{} (ex: now-5m, now-1h, now-30s)", offset);
+    let (sign, rest) = if let Some(rest) = offset.strip_prefix('-') {
+        (-1, rest)
+    } else if let Some(rest) = offset.strip_prefix('+').or_else(|| offset.strip_prefix(' ')) {
+        (1, rest)
+    } else {
+        return Err(invalid());
+    };
+    parse_duration(rest)
+        .and_then(|delta| now_ms.checked_add(sign * delta))
+        .ok_or_else(invalid)
+}
+
+// "500ms", "30s", "5m", "1h", "2d", "1w"
+pub fn parse_duration(value: &str) -> Option<i64> {
+    let digits = value.chars().take_while(|c| c.is_ascii_digit()).count();
+    let (amount, unit) = value.split_at(digits);
+    let amount: i64 = amount.parse().ok()?;
+    let unit_ms = match unit {
+        "ms" => 1,
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        "d" => DAY_MS,
+        "w" => 7 * DAY_MS,
+        _ => return None,
+    };
+    amount.checked_mul(unit_ms)
+}
+
+// largura de cada ponto da série: group_by=second|minute|hour|day ou interval=30s, 5m, 1500 (ms)
+pub fn parse_interval(group_by: Option<&str>, interval: Option<&str>) -> Result<Option<i64>, RangeError> {
+    let group_by = group_by.map(str::trim).filter(|v| !v.is_empty());
+    let interval = interval.map(str::trim).filter(|v| !v.is_empty());
+    let width = match (group_by, interval) {
+        (None, None) => return Ok(None),
+        (Some(_), Some(_)) => {
+            return Err(RangeError::new(None, "use group_by ou interval, não os dois".to_string()));
+        }
+        (Some(group_by), None) => match group_by {
+            "second" => 1_000,
+            "minute" => 60_000,
+            "hour" => 3_600_000,
+            "day" => DAY_MS,
+            _ => {
+                return Err(RangeError::new(
+                    Some("group_by"),
+                    format!("group_by inválido: {:?} (use second, minute, hour ou day)", group_by),
+                ));
+            }
+        },
+        (None, Some(interval)) => interval.parse::<i64>().ok()
+            .or_else(|| parse_duration(interval))
+            .ok_or_else(|| RangeError::new(
+                Some("interval"),
+                format!("interval inválido: {:?} (ex: 500ms, 30s, 5m, 1h ou ms)", interval),
+            ))?,
+    };
+    if width <= 0 {
+        return Err(RangeError::new(Some("interval"), "interval deve ser maior que zero".to_string()));
+    }
+    Ok(Some(width))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: i64 = 1_752_148_800_000; // 2025-07-10T12:00:00Z
+
+    fn instant(value: &str) -> i64 {
+        let period = parse_time(value, NOW).unwrap();
+        assert_eq!(period.end, period.start + 1, "{value}");
+        period.start
+    }
+
+    #[test]
+    fn parse_time_epochs() {
+        assert_eq!(instant("1752148800"), NOW);
+        assert_eq!(instant("1752148800000"), NOW);
+        assert_eq!(instant("0"), 0);
+        assert_eq!(instant("-60"), -60_000);
+    }
+
+    #[test]
+    fn parse_time_rejects_out_of_range_epochs() {
+        assert!(parse_time("9223372036854775807", NOW).is_err());
+        assert!(parse_time("-9223372036854775808", NOW).is_err());
+        assert!(parse_time("99999999999", NOW).is_ok());
+        assert!(parse_time("99999999999999999", NOW).is_err());
+    }
+
+    #[test]
+    fn parse_time_rfc3339_with_offsets() {
+        assert_eq!(instant("2025-07-10T12:00:00Z"), NOW);
+        assert_eq!(instant("2025-07-10T12:00:00.000Z"), NOW);
+        assert_eq!(instant("2025-07-10T14:00:00+02:00"), NOW);
+        assert_eq!(instant("2025-07-10T09:00:00-03:00"), NOW);
+        // "+" sem encode na query string
+        assert_eq!(instant("2025-07-10T14:00:00 02:00"), NOW);
+    }
+
+    #[test]
+    fn parse_time_naive_is_utc() {
+        assert_eq!(instant("2025-07-10T12:00:00"), NOW);
+        assert_eq!(instant("2025-07-10 12:00:00.000"), NOW);
+    }
+
+    #[test]
+    fn parse_time_date_only_is_the_whole_day() {
+        let period = parse_time("2025-07-10", NOW).unwrap();
+        assert_eq!(period, Period { start: NOW - 12 * 3_600_000, end: NOW + 12 * 3_600_000 });
+    }
+
+    #[test]
+    fn parse_time_relative() {
+        assert_eq!(instant("now"), NOW);
+        assert_eq!(instant("now-5m"), NOW - 300_000);
+        assert_eq!(instant("now+1h"), NOW + 3_600_000);
+        assert_eq!(instant("now 1h"), NOW + 3_600_000);
+        assert_eq!(instant("now-500ms"), NOW - 500);
+        assert_eq!(instant("now-1w"), NOW - 7 * DAY_MS);
+        for invalid in ["now-", "now-5", "now-5y", "now*5m", "now-€", "now-9223372036854775807w"] {
+            assert!(parse_time(invalid, NOW).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn parse_time_rejects_garbage() {
+        for invalid in ["", "ontem", "2025-13-01", "2025-07-10T25:00:00Z", "12:00"] {
+            assert!(parse_time(invalid, NOW).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn parse_bounds_notation() {
+        use Bound::*;
+        assert_eq!(parse_bounds(None), Ok((Inclusive, Inclusive)));
+        assert_eq!(parse_bounds(Some(" ")), Ok((Inclusive, Inclusive)));
+        assert_eq!(parse_bounds(Some("[]")), Ok((Inclusive, Inclusive)));
+        assert_eq!(parse_bounds(Some("[)")), Ok((Inclusive, Exclusive)));
+        assert_eq!(parse_bounds(Some("(]")), Ok((Exclusive, Inclusive)));
+        assert_eq!(parse_bounds(Some("()")), Ok((Exclusive, Exclusive)));
+        for invalid in ["[", "[]]", ")(", "{}", "ab"] {
+            assert!(parse_bounds(Some(invalid)).is_err(), "{invalid}");
+        }
+    }
+
+    #[test]
+    fn parse_interval_options() {
+        assert_eq!(parse_interval(None, None).unwrap(), None);
+        assert_eq!(parse_interval(Some("second"), None).unwrap(), Some(1_000));
+        assert_eq!(parse_interval(Some("minute"), None).unwrap(), Some(60_000));
+        assert_eq!(parse_interval(Some("hour"), None).unwrap(), Some(3_600_000));
+        assert_eq!(parse_interval(Some("day"), None).unwrap(), Some(DAY_MS));
+        assert_eq!(parse_interval(None, Some("30s")).unwrap(), Some(30_000));
+        assert_eq!(parse_interval(None, Some("1500")).unwrap(), Some(1_500));
+        assert_eq!(parse_interval(None, Some("5m")).unwrap(), Some(300_000));
+    }
+
+    #[test]
+    fn parse_interval_errors() {
+        assert_eq!(parse_interval(Some("minute"), Some("5m")).unwrap_err().field, None);
+        assert_eq!(parse_interval(Some("year"), None).unwrap_err().field, Some("group_by"));
+        assert_eq!(parse_interval(None, Some("5y")).unwrap_err().field, Some("interval"));
+        assert_eq!(parse_interval(None, Some("0")).unwrap_err().field, Some("interval"));
+        assert_eq!(parse_interval(None, Some("-10")).unwrap_err().field, Some("interval"));
+    }
+
+    #[test]
+    fn resolve_bounds() {
+        let range = |from, to, bounds| TimeRange::resolve(from, to, bounds, NOW, i64::MAX);
+        assert_eq!(range(None, None, None).unwrap(), TimeRange { from: 0, to: i64::MAX });
+        assert_eq!(range(Some("1000"), Some("2000"), None).unwrap(), TimeRange { from: 1_000_000, to: 2_000_000 });
+        assert_eq!(range(Some("1000"), Some("2000"), Some("()")).unwrap(), TimeRange { from: 1_000_001, to: 1_999_999 });
+        // data sem hora: [] pega o dia inteiro, [) termina no início do dia
+        let day = NOW - 12 * 3_600_000;
+        assert_eq!(range(Some("2025-07-10"), Some("2025-07-10"), None).unwrap(), TimeRange { from: day, to: day + DAY_MS - 1 });
+        assert_eq!(range(Some("2025-07-10"), Some("2025-07-11"), Some("[)")).unwrap(), TimeRange { from: day, to: day + DAY_MS - 1 });
+        assert_eq!(range(Some("2000"), Some("1000"), None).unwrap_err().field, None);
+        // to só com a data vai até o fim do dia, from no meio dele continua antes
+        assert_eq!(range(Some("2025-07-10T12:00:00Z"), Some("2025-07-10"), None).unwrap(), TimeRange { from: NOW, to: day + DAY_MS - 1 });
+        assert_eq!(range(Some("2025-07-10T12:00:00Z"), Some("2025-07-10"), Some("[)")).unwrap_err().field, None);
+        // o mesmo instante com um lado aberto não tem nada dentro
+        assert!(range(Some("1000"), Some("1000"), None).is_ok());
+        assert_eq!(range(Some("1000"), Some("1000"), Some("[)")).unwrap_err().field, None);
+        assert_eq!(range(Some("x"), None, None).unwrap_err().field, Some("from"));
+        assert_eq!(range(None, Some("9223372036854775807"), None).unwrap_err().field, Some("to"));
+    }
+}
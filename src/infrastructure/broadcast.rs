@@ -0,0 +1,63 @@
+use crate::infrastructure::config::HEALTH_BROADCASTER_NAME;
+use crate::infrastructure::pubsub::{run_publisher, run_subscriber};
+use crate::infrastructure::ws::{run_master, run_slave};
+use crate::infrastructure::ws_auth::auth_enabled;
+use once_cell::sync::Lazy;
+use redis::aio::ConnectionManager;
+
+// leva o health (e as estatísticas) do líder para as outras instâncias e os relatórios delas de volta
+pub trait HealthBroadcaster: Send + Sync {
+    fn name(&self) -> &'static str;
+    // sobe as tasks de publicação e recebimento; quem é líder é decidido pelo lease, não aqui
+    fn start(&self, conn: ConnectionManager);
+}
+
+pub static HEALTH_BROADCASTER: Lazy<Box<dyn HealthBroadcaster>> = Lazy::new(|| {
+    let broadcaster = broadcaster_from_name(HEALTH_BROADCASTER_NAME.as_str());
+    println!("[SYNC] Distribuição de health: {}", broadcaster.name());
+    broadcaster
+});
+
+pub fn broadcaster_from_name(name: &str) -> Box<dyn HealthBroadcaster> {
+    match name {
+        "redis" | "pubsub" => Box::new(RedisPubSubBroadcaster),
+        "websocket" | "ws" => Box::new(WebSocketBroadcaster),
+        other => {
+            eprintln!("[SYNC] Distribuição desconhecida {}, usando websocket", other);
+            Box::new(WebSocketBroadcaster)
+        }
+    }
+}
+
+// líder serve na porta 9001, os outros conectam no endereço anunciado no lease
+pub struct WebSocketBroadcaster;
+
+impl HealthBroadcaster for WebSocketBroadcaster {
+    fn name(&self) -> &'static str {
+        "websocket"
+    }
+
+    fn start(&self, conn: ConnectionManager) {
+        if auth_enabled() {
+            println!("[SYNC] WebSocket interno com handshake e frames autenticados (HMAC)");
+        } else {
+            eprintln!("[SYNC] WS_SHARED_SECRET não definido, WebSocket interno sem autenticação");
+        }
+        run_master();
+        run_slave(conn);
+    }
+}
+
+// tudo pelo redis que já existe: canais de pub/sub, sem porta nem endereço de líder
+pub struct RedisPubSubBroadcaster;
+
+impl HealthBroadcaster for RedisPubSubBroadcaster {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    fn start(&self, conn: ConnectionManager) {
+        run_publisher(conn);
+        run_subscriber();
+    }
+}
@@ -0,0 +1,317 @@
+use crate::domain::entities::ProcessorOutcome;
+use crate::infrastructure::config::{
+    CB_ERROR_RATE_THRESHOLD, CB_FAILURE_THRESHOLD, CB_HALF_OPEN_PROBES, CB_MIN_CALLS, CB_OPEN_MS, CB_WINDOW_SIZE,
+};
+use crate::infrastructure::utils::now_unix_ms;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug, Clone)]
+pub struct BreakerConfig {
+    pub failure_threshold: u32,
+    pub error_rate_threshold: f64,
+    pub window_size: usize,
+    pub min_calls: usize,
+    pub open_duration: Duration,
+    pub half_open_probes: u32,
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        BreakerConfig {
+            failure_threshold: *CB_FAILURE_THRESHOLD,
+            error_rate_threshold: *CB_ERROR_RATE_THRESHOLD,
+            window_size: *CB_WINDOW_SIZE,
+            min_calls: *CB_MIN_CALLS,
+            open_duration: Duration::from_millis(*CB_OPEN_MS),
+            half_open_probes: *CB_HALF_OPEN_PROBES,
+        }
+    }
+}
+
+struct BreakerState {
+    state: CircuitState,
+    consecutive_failures: u32,
+    // últimos resultados, true = erro
+    window: VecDeque<bool>,
+    opened_at_ms: u64,
+    probes_in_flight: u32,
+    probe_successes: u32,
+}
+
+pub struct CircuitBreaker {
+    processor: String,
+    config: BreakerConfig,
+    inner: Mutex<BreakerState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(processor: &str, config: BreakerConfig) -> Self {
+        CircuitBreaker {
+            processor: processor.to_string(),
+            config,
+            inner: Mutex::new(BreakerState {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+                window: VecDeque::new(),
+                opened_at_ms: 0,
+                probes_in_flight: 0,
+                probe_successes: 0,
+            }),
+        }
+    }
+
+    pub fn state(&self) -> CircuitState {
+        self.inner.lock().unwrap().state
+    }
+
+    // quando o circuito abriu, se estiver aberto
+    pub fn opened_at_ms(&self) -> Option<u64> {
+        let inner = self.inner.lock().unwrap();
+        (inner.state == CircuitState::Open).then_some(inner.opened_at_ms)
+    }
+
+    // para o roteamento: aberto e ainda dentro do tempo de espera não recebe tráfego
+    pub fn allows_traffic(&self) -> bool {
+        let inner = self.inner.lock().unwrap();
+        inner.state != CircuitState::Open || self.open_expired(&inner)
+    }
+
+    // pede permissão para enviar um pagamento; no half-open só passam alguns probes
+    pub fn try_acquire(&self) -> bool {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.state == CircuitState::Open && self.open_expired(&inner) {
+            self.transition(&mut inner, CircuitState::HalfOpen);
+        }
+        match inner.state {
+            CircuitState::Closed => true,
+            CircuitState::Open => false,
+            CircuitState::HalfOpen => {
+                if inner.probes_in_flight < self.config.half_open_probes {
+                    inner.probes_in_flight += 1;
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    pub fn record(&self, outcome: ProcessorOutcome) {
+        // rejeição permanente é problema do pagamento, não do processador
+        if outcome == ProcessorOutcome::PermanentRejection {
+            let mut inner = self.inner.lock().unwrap();
+            inner.probes_in_flight = inner.probes_in_flight.saturating_sub(1);
+            return;
+        }
+        let failed = outcome.is_error();
+        let mut inner = self.inner.lock().unwrap();
+        match inner.state {
+            CircuitState::HalfOpen => {
+                inner.probes_in_flight = inner.probes_in_flight.saturating_sub(1);
+                if failed {
+                    self.transition(&mut inner, CircuitState::Open);
+                } else {
+                    inner.probe_successes += 1;
+                    if inner.probe_successes >= self.config.half_open_probes {
+                        self.transition(&mut inner, CircuitState::Closed);
+                    }
+                }
+            }
+            CircuitState::Closed => {
+                inner.window.push_back(failed);
+                while inner.window.len() > self.config.window_size {
+                    inner.window.pop_front();
+                }
+                if failed {
+                    inner.consecutive_failures += 1;
+                } else {
+                    inner.consecutive_failures = 0;
+                }
+                let errors = inner.window.iter().filter(|e| **e).count();
+                let error_rate = errors as f64 / inner.window.len() as f64;
+                if inner.consecutive_failures >= self.config.failure_threshold
+                    || (inner.window.len() >= self.config.min_calls && error_rate >= self.config.error_rate_threshold) {
+                    self.transition(&mut inner, CircuitState::Open);
+                }
+            }
+            // resposta atrasada de uma chamada anterior, o circuito já está aberto
+            CircuitState::Open => {}
+        }
+    }
+
+    // sinal extra vindo do /payments/service-health: só abre o circuito. Health ok não fecha nem
+    // adianta o half-open, quem decide a volta é o open_duration e as chamadas de teste
+    pub fn record_health(&self, failing: bool) {
+        let mut inner = self.inner.lock().unwrap();
+        if failing && inner.state != CircuitState::Open {
+            self.transition(&mut inner, CircuitState::Open);
+        }
+    }
+
+    fn open_expired(&self, inner: &BreakerState) -> bool {
+        now_unix_ms().saturating_sub(inner.opened_at_ms) >= self.config.open_duration.as_millis() as u64
+    }
+
+    fn transition(&self, inner: &mut BreakerState, state: CircuitState) {
+        if inner.state == state {
+            return;
+        }
+        eprintln!("[CIRCUIT] {}: {:?} -> {:?}", self.processor, inner.state, state);
+        inner.state = state;
+        inner.consecutive_failures = 0;
+        inner.window.clear();
+        inner.probes_in_flight = 0;
+        inner.probe_successes = 0;
+        if state == CircuitState::Open {
+            inner.opened_at_ms = now_unix_ms();
+        }
+    }
+}
+
+static CIRCUIT_BREAKERS: Lazy<Mutex<BTreeMap<String, Arc<CircuitBreaker>>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+pub fn circuit_breaker(processor: &str) -> Arc<CircuitBreaker> {
+    let mut breakers = CIRCUIT_BREAKERS.lock().unwrap();
+    breakers
+        .entry(processor.to_string())
+        .or_insert_with(|| Arc::new(CircuitBreaker::new(processor, BreakerConfig::default())))
+        .clone()
+}
+
+pub fn circuit_states() -> BTreeMap<String, CircuitState> {
+    CIRCUIT_BREAKERS.lock().unwrap()
+        .iter()
+        .map(|(processor, breaker)| (processor.clone(), breaker.state()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BreakerConfig, CircuitBreaker, CircuitState};
+    use crate::domain::entities::ProcessorOutcome::{self, PermanentRejection, Success, TransientFailure};
+    use std::time::Duration;
+
+    const OPEN: Duration = Duration::from_millis(30);
+
+    fn breaker(failure_threshold: u32, min_calls: usize) -> CircuitBreaker {
+        CircuitBreaker::new("test", BreakerConfig {
+            failure_threshold,
+            error_rate_threshold: 0.5,
+            window_size: 10,
+            min_calls,
+            open_duration: OPEN,
+            half_open_probes: 2,
+        })
+    }
+
+    fn record_all(breaker: &CircuitBreaker, outcomes: &[ProcessorOutcome]) {
+        for outcome in outcomes {
+            breaker.record(*outcome);
+        }
+    }
+
+    // espera o open_duration passar e pega o primeiro probe
+    fn half_open(breaker: &CircuitBreaker) {
+        std::thread::sleep(OPEN + Duration::from_millis(5));
+        assert!(breaker.allows_traffic());
+        assert!(breaker.try_acquire());
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+    }
+
+    #[test]
+    fn consecutive_failures_open_the_circuit() {
+        let breaker = breaker(3, 100);
+        record_all(&breaker, &[TransientFailure, TransientFailure, Success, TransientFailure, TransientFailure]);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        breaker.record(TransientFailure);
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(breaker.opened_at_ms().is_some());
+        assert!(!breaker.allows_traffic());
+        assert!(!breaker.try_acquire());
+    }
+
+    #[test]
+    fn error_rate_opens_only_after_min_calls() {
+        let breaker = breaker(100, 4);
+        record_all(&breaker, &[TransientFailure, Success, TransientFailure]);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        breaker.record(Success);
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+
+    #[test]
+    fn permanent_rejection_is_not_a_failure() {
+        let breaker = breaker(2, 2);
+        record_all(&breaker, &[PermanentRejection, PermanentRejection, PermanentRejection, TransientFailure]);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+    }
+
+    #[test]
+    fn half_open_limits_probes_and_closes_after_successes() {
+        let breaker = breaker(1, 100);
+        breaker.record(TransientFailure);
+        half_open(&breaker);
+        assert!(breaker.try_acquire());
+        assert!(!breaker.try_acquire());
+
+        breaker.record(Success);
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        breaker.record(Success);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+        assert!(breaker.try_acquire());
+    }
+
+    #[test]
+    fn failed_probe_reopens_the_circuit() {
+        let breaker = breaker(1, 100);
+        breaker.record(TransientFailure);
+        half_open(&breaker);
+
+        breaker.record(TransientFailure);
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.try_acquire());
+    }
+
+    #[test]
+    fn permanent_rejection_frees_a_probe() {
+        let breaker = breaker(1, 100);
+        breaker.record(TransientFailure);
+        half_open(&breaker);
+        assert!(breaker.try_acquire());
+
+        breaker.record(PermanentRejection);
+        assert_eq!(breaker.state(), CircuitState::HalfOpen);
+        assert!(breaker.try_acquire());
+    }
+
+    #[test]
+    fn health_only_opens_the_circuit() {
+        let breaker = breaker(100, 100);
+        breaker.record_health(false);
+        assert_eq!(breaker.state(), CircuitState::Closed);
+
+        breaker.record_health(true);
+        assert_eq!(breaker.state(), CircuitState::Open);
+        // health ok não fecha nem adianta o half-open
+        breaker.record_health(false);
+        assert_eq!(breaker.state(), CircuitState::Open);
+        assert!(!breaker.try_acquire());
+
+        half_open(&breaker);
+        breaker.record_health(true);
+        assert_eq!(breaker.state(), CircuitState::Open);
+    }
+}
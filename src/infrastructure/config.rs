@@ -1,29 +1,52 @@
 use std::env;
+use std::str::FromStr;
 use std::sync::atomic::AtomicBool;
 use once_cell::sync::Lazy;
 use std::sync::Arc;
 use tokio::sync::{RwLock};
-use crate::domain::entities::HealthStatusAll;
+use crate::domain::entities::{HealthStatusAll, ProcessorConfig, ProcessorOutcome};
+use std::collections::BTreeMap;
+use crate::domain::retry::RetryPolicy;
+use std::time::Duration;
 use crate::HealthResponse;
 
+fn env_parse<T: FromStr>(key: &str, default: T) -> T {
+    env::var(key)
+        .ok()
+        .and_then(|s| s.parse::<T>().ok())
+        .unwrap_or(default)
+}
+
 pub const QUEUE_KEY: &str = "queue";
 pub const QUEUE_FAILED_KEY: &str = "queue:failed";
+pub const QUEUE_PROCESSING_KEY: &str = "queue:processing";
+pub const QUEUE_DELAYED_KEY: &str = "queue:delayed";
+pub const ACCEPTED_KEY: &str = "payments:accepted";
+pub const DISPATCH_KEY: &str = "payments:processor";
+pub const RECORDED_KEY: &str = "summary:recorded";
+pub const SUMMARY_BUCKET_WIDTH_KEY: &str = "summary:bucket_width";
+pub const SUMMARY_BUCKET_SINCE_KEY: &str = "summary:bucket_since";
+pub const LEADER_KEY: &str = "health:leader";
+pub const INSTANCE_KEY: &str = "instance";
+pub const HEALTH_RATE_LIMIT_KEY: &str = "health:ratelimit";
+pub const HEALTH_SNAPSHOT_KEY: &str = "health:snapshot";
+pub const HEALTH_SYNC_CHANNEL: &str = "health:sync";
+pub const HEALTH_REPORT_CHANNEL: &str = "health:reports";
 pub static HEALTH_STATUS: Lazy<AtomicBool> = Lazy::new(||AtomicBool::new(true));
 
 pub static GLOBAL_HEALTH_STATUS: Lazy<Arc<RwLock<HealthStatusAll>>> = Lazy::new(|| {
-    Arc::new(RwLock::new(HealthStatusAll {
-        default: HealthResponse {
-            failing: false,
-            min_response_time: 0,
-            failing_since: None
-        },
-        fallback: HealthResponse {
+    // até o primeiro health check, só o processador de maior prioridade parece rápido
+    let processors = PROCESSORS.iter().enumerate().map(|(index, processor)| {
+        (processor.name.clone(), HealthResponse {
             failing: false,
-            min_response_time: 5000,
+            min_response_time: if index == 0 { 0 } else { 5000 },
             failing_since: None
-        }
-    }))
+        })
+    }).collect();
+    Arc::new(RwLock::new(HealthStatusAll { processors, updated_at: 0 }))
 });
+
+// configuração legada com dois processadores, usada quando PAYMENT_PROCESSORS não está definido
 pub static PAYMENT_PROCESSOR_DEFAULT_URL: Lazy<String> = Lazy::new(|| {
     env::var("PAYMENT_PROCESSOR_DEFAULT_URL").unwrap_or_else(|_| "http://localhost:8001".to_string())
 });
@@ -31,14 +54,258 @@ pub static PAYMENT_PROCESSOR_FALLBACK_URL: Lazy<String> = Lazy::new(|| {
     env::var("PAYMENT_PROCESSOR_FALLBACK_URL").unwrap_or_else(|_| "http://localhost:8002".to_string())
 });
 
+pub static PAYMENT_PROCESSOR_DEFAULT_FEE: Lazy<f64> = Lazy::new(|| env_parse("PAYMENT_PROCESSOR_DEFAULT_FEE", 0.05));
+pub static PAYMENT_PROCESSOR_FALLBACK_FEE: Lazy<f64> = Lazy::new(|| env_parse("PAYMENT_PROCESSOR_FALLBACK_FEE", 0.15));
+
+// sempre presentes no /payments-summary, zerados quando não configurados
+pub const LEGACY_PROCESSORS: [&str; 2] = ["default", "fallback"];
+
+// campos que ficam ao lado dos nomes de processador nos JSONs achatados (health, summary, série)
+const RESERVED_PROCESSOR_NAMES: [&str; 3] = ["updatedAt", "series", "from"];
+
+// o nome é a chave do processador no redis e nos JSONs: repetido, dois processadores somariam juntos
+pub fn check_processor_names(processors: &[ProcessorConfig]) -> Result<(), String> {
+    if let Some(processor) = processors.iter().find(|p| RESERVED_PROCESSOR_NAMES.contains(&p.name.as_str())) {
+        return Err(format!("nome de processador reservado: {}", processor.name));
+    }
+    for (index, processor) in processors.iter().enumerate() {
+        if processors[..index].iter().any(|p| p.name == processor.name) {
+            return Err(format!("nome de processador repetido: {}", processor.name));
+        }
+    }
+    Ok(())
+}
+
+// PAYMENT_PROCESSORS='[{"name":"default","url":"http://...","priority":0,"fee":0.05,"weight":80}, ...]'
+// ordenado por prioridade (menor primeiro)
+pub static PROCESSORS: Lazy<Vec<ProcessorConfig>> = Lazy::new(|| {
+    let mut processors = match env::var("PAYMENT_PROCESSORS") {
+        Ok(json) => serde_json::from_str::<Vec<ProcessorConfig>>(&json)
+            .expect("PAYMENT_PROCESSORS inválido"),
+        Err(_) => vec![
+            ProcessorConfig {
+                name: "default".to_string(),
+                url: PAYMENT_PROCESSOR_DEFAULT_URL.to_string(),
+                priority: 0,
+                fee: *PAYMENT_PROCESSOR_DEFAULT_FEE,
+                weight: *ROUTING_DEFAULT_WEIGHT,
+            },
+            ProcessorConfig {
+                name: "fallback".to_string(),
+                url: PAYMENT_PROCESSOR_FALLBACK_URL.to_string(),
+                priority: 1,
+                fee: *PAYMENT_PROCESSOR_FALLBACK_FEE,
+                weight: *ROUTING_FALLBACK_WEIGHT,
+            },
+        ],
+    };
+    assert!(!processors.is_empty(), "nenhum processador configurado");
+    if let Err(e) = check_processor_names(&processors) {
+        panic!("PAYMENT_PROCESSORS inválido: {}", e);
+    }
+    processors.sort_by_key(|p| p.priority);
+    processors
+});
+
+pub fn processor_config(processor: &str) -> Option<&'static ProcessorConfig> {
+    PROCESSORS.iter().find(|p| p.name == processor)
+}
+
+// próximo processador na ordem de prioridade, para o failover
+pub fn next_processor(processor: &str) -> Option<&'static ProcessorConfig> {
+    let index = PROCESSORS.iter().position(|p| p.name == processor)?;
+    PROCESSORS.get(index + 1)
+}
+
+pub fn processor_fee(processor: &str) -> f64 {
+    processor_config(processor).map(|p| p.fee).unwrap_or(0.0)
+}
+
+// fim de intervalo aberto no summary: 9999-12-31T23:59:59.999Z
+pub const MAX_TIMESTAMP_MS: i64 = 253_402_300_799_999;
+
+// largura dos buckets de contadores do summary (1000 = por segundo, 60000 = por minuto);
+// trocar a largura com dados no redis é seguro, o que veio antes da troca é contado item a item.
+// Vale para o cluster a da última instância que subiu (redis::adopt_bucket_width)
+pub static SUMMARY_BUCKET_MS: Lazy<u64> = Lazy::new(|| env_parse::<u64>("SUMMARY_BUCKET_MS", 1000).max(1));
+
 pub static REDIS_URL: Lazy<String> = Lazy::new(|| {
     env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379/".to_string())
 });
 
-pub static INSTANCE_ROLE: Lazy<String> = Lazy::new(|| {
-    env::var("INSTANCE_ROLE").unwrap_or_else(|_| "none".to_string())
+pub static INSTANCE_ID: Lazy<String> = Lazy::new(|| {
+    env::var("INSTANCE_ID")
+        .or_else(|_| env::var("HOSTNAME"))
+        .unwrap_or_else(|_| "local".to_string())
+});
+
+// gerado a cada processo, nunca se repete: identifica o dono do lease mesmo com INSTANCE_ID igual
+pub static PROCESS_TOKEN: Lazy<String> = Lazy::new(|| format!("{:032x}", rand::random::<u128>()));
+
+pub static MAX_PAYMENT_ATTEMPTS: Lazy<u32> = Lazy::new(|| env_parse("MAX_PAYMENT_ATTEMPTS", 10));
+
+pub static ROUTING_STRATEGY_NAME: Lazy<String> = Lazy::new(|| {
+    env::var("ROUTING_STRATEGY").unwrap_or_else(|_| "default-first".to_string())
 });
 
-pub static WS_MASTER_URL: Lazy<String> = Lazy::new(|| {
-    env::var("WS_MASTER_URL").unwrap_or_else(|_| "ws://127.0.0.1:9001".to_string())
+// fração do valor que se considera perdida por ms de latência ao comparar processadores
+pub static ROUTING_LATENCY_COST_PER_MS: Lazy<f64> = Lazy::new(|| env_parse("ROUTING_LATENCY_COST_PER_MS", 0.0001));
+
+// pesos do weighted-split na configuração legada
+pub static ROUTING_DEFAULT_WEIGHT: Lazy<u32> = Lazy::new(|| env_parse("ROUTING_DEFAULT_WEIGHT", 80));
+
+pub static ROUTING_FALLBACK_WEIGHT: Lazy<u32> = Lazy::new(|| env_parse("ROUTING_FALLBACK_WEIGHT", 20));
+
+// o teto também é o timeout global do client HTTP (main.rs), o timeout por requisição nunca passa dele
+pub static PROCESSOR_TIMEOUT_CEILING_MS: Lazy<u64> = Lazy::new(|| env_parse("PROCESSOR_TIMEOUT_CEILING_MS", 2000));
+pub static PROCESSOR_TIMEOUT_FLOOR_MS: Lazy<u64> = Lazy::new(|| {
+    let floor = env_parse("PROCESSOR_TIMEOUT_FLOOR_MS", 100);
+    assert!(
+        floor <= *PROCESSOR_TIMEOUT_CEILING_MS,
+        "PROCESSOR_TIMEOUT_FLOOR_MS ({}) maior que PROCESSOR_TIMEOUT_CEILING_MS ({})",
+        floor,
+        *PROCESSOR_TIMEOUT_CEILING_MS,
+    );
+    floor
 });
+
+fn load_retry_policy(processor: &str, max_attempts: u32) -> RetryPolicy {
+    let prefix = format!("RETRY_{}", processor.to_uppercase().replace('-', "_"));
+    let retryable = env::var(format!("{}_RETRYABLE", prefix))
+        .map(|s| s.split(',').filter_map(|o| ProcessorOutcome::parse(o.trim())).collect())
+        .unwrap_or_else(|_| vec![
+            ProcessorOutcome::TransientFailure,
+            ProcessorOutcome::Timeout,
+            ProcessorOutcome::ConnectionError,
+            ProcessorOutcome::ResponseError,
+        ]);
+    // fora de 0..=1 (ou NaN) o fator sorteado no delay poderia ficar negativo
+    let jitter: f64 = env_parse(&format!("{}_JITTER", prefix), 0.2);
+    let jitter = if jitter.is_nan() { 0.2 } else { jitter.clamp(0.0, 1.0) };
+    RetryPolicy {
+        max_attempts: env_parse(&format!("{}_MAX_ATTEMPTS", prefix), max_attempts),
+        base_delay: Duration::from_millis(env_parse(&format!("{}_BASE_DELAY_MS", prefix), 100)),
+        max_delay: Duration::from_millis(env_parse(&format!("{}_MAX_DELAY_MS", prefix), 5000)),
+        jitter,
+        deadline: Duration::from_millis(env_parse(&format!("{}_DEADLINE_MS", prefix), 60000)),
+        retryable,
+    }
+}
+
+// o processador principal tem mais tentativas, os demais são só para failover
+pub static RETRY_POLICIES: Lazy<BTreeMap<String, RetryPolicy>> = Lazy::new(|| {
+    PROCESSORS.iter().enumerate().map(|(index, processor)| {
+        let max_attempts = if index == 0 { 5 } else { 3 };
+        (processor.name.clone(), load_retry_policy(&processor.name, max_attempts))
+    }).collect()
+});
+
+static UNKNOWN_RETRY_POLICY: Lazy<RetryPolicy> = Lazy::new(|| load_retry_policy("unknown", 3));
+
+pub fn retry_policy(processor: &str) -> &'static RetryPolicy {
+    RETRY_POLICIES.get(processor).unwrap_or(&UNKNOWN_RETRY_POLICY)
+}
+
+// por quanto tempo um correlationId aceito barra um POST repetido; nunca menos que o maior prazo
+// de retentativa mais o maior atraso, senão um retry ainda pendente poderia ganhar uma cópia na fila.
+// Depois disso o RECORDED_KEY continua barrando o que já foi contabilizado
+pub static ACCEPTED_TTL_MS: Lazy<u64> = Lazy::new(|| {
+    let budget = RETRY_POLICIES.values().chain([&*UNKNOWN_RETRY_POLICY])
+        .map(|policy| (policy.deadline + policy.max_delay).as_millis() as u64)
+        .max()
+        .unwrap_or(0);
+    env_parse("ACCEPTED_TTL_MS", 0).max(budget + 60_000)
+});
+
+pub static CB_FAILURE_THRESHOLD: Lazy<u32> = Lazy::new(|| env_parse("CB_FAILURE_THRESHOLD", 5));
+pub static CB_ERROR_RATE_THRESHOLD: Lazy<f64> = Lazy::new(|| env_parse("CB_ERROR_RATE_THRESHOLD", 0.5));
+pub static CB_WINDOW_SIZE: Lazy<usize> = Lazy::new(|| env_parse("CB_WINDOW_SIZE", 20));
+pub static CB_MIN_CALLS: Lazy<usize> = Lazy::new(|| env_parse("CB_MIN_CALLS", 10));
+pub static CB_OPEN_MS: Lazy<u64> = Lazy::new(|| env_parse("CB_OPEN_MS", 2000));
+pub static CB_HALF_OPEN_PROBES: Lazy<u32> = Lazy::new(|| env_parse("CB_HALF_OPEN_PROBES", 3));
+
+// endereço que os outros usam para conectar no WebSocket quando essa instância é a líder
+pub static WS_ADVERTISE_URL: Lazy<String> = Lazy::new(|| {
+    env::var("WS_ADVERTISE_URL").unwrap_or_else(|_| {
+        let scheme = if WS_TLS_CERT.is_some() { "wss" } else { "ws" };
+        format!("{}://{}:9001", scheme, INSTANCE_ID.as_str())
+    })
+});
+
+fn env_optional(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|value| !value.is_empty())
+}
+
+// token das rotas /admin (header x-admin-token); sem ele as rotas /admin ficam fechadas
+pub static ADMIN_TOKEN: Lazy<Option<String>> = Lazy::new(|| env_optional("ADMIN_TOKEN"));
+
+// segredo compartilhado entre as instâncias; sem ele o WebSocket interno não é autenticado
+pub static WS_SHARED_SECRET: Lazy<Option<String>> = Lazy::new(|| env_optional("WS_SHARED_SECRET"));
+// diferença máxima entre o relógio de quem assinou e o de quem recebe
+pub static WS_AUTH_MAX_SKEW_MS: Lazy<u64> = Lazy::new(|| env_parse("WS_AUTH_MAX_SKEW_MS", 30000));
+// origens de navegador aceitas no handshake (separadas por vírgula); vazio recusa qualquer Origin
+pub static WS_ALLOWED_ORIGINS: Lazy<Vec<String>> = Lazy::new(|| {
+    env::var("WS_ALLOWED_ORIGINS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|origin| origin.trim().to_string())
+        .filter(|origin| !origin.is_empty())
+        .collect()
+});
+// certificado e chave (PEM) do servidor; com os dois definidos o listener passa a usar TLS
+pub static WS_TLS_CERT: Lazy<Option<String>> = Lazy::new(|| env_optional("WS_TLS_CERT"));
+pub static WS_TLS_KEY: Lazy<Option<String>> = Lazy::new(|| env_optional("WS_TLS_KEY"));
+// CA (PEM) usada pelo cliente para validar o líder em wss://
+pub static WS_TLS_CA: Lazy<Option<String>> = Lazy::new(|| env_optional("WS_TLS_CA"));
+
+pub static LEADER_LEASE_MS: Lazy<u64> = Lazy::new(|| env_parse("LEADER_LEASE_MS", 3000));
+pub static LEADER_RENEW_MS: Lazy<u64> = Lazy::new(|| env_parse("LEADER_RENEW_MS", 1000));
+// os processadores aceitam no máximo 1 health check a cada 5s, somando todas as instâncias
+pub static HEALTH_CHECK_INTERVAL_MS: Lazy<u64> = Lazy::new(|| env_parse("HEALTH_CHECK_INTERVAL_MS", 5000));
+pub static WS_RECONNECT_BASE_MS: Lazy<u64> = Lazy::new(|| env_parse("WS_RECONNECT_BASE_MS", 100));
+pub static WS_RECONNECT_MAX_MS: Lazy<u64> = Lazy::new(|| env_parse("WS_RECONNECT_MAX_MS", 5000));
+// snapshot completo periódico, as mudanças de health vão na hora
+pub static WS_SNAPSHOT_INTERVAL_MS: Lazy<u64> = Lazy::new(|| env_parse("WS_SNAPSHOT_INTERVAL_MS", 2000));
+pub static WS_REPORT_INTERVAL_MS: Lazy<u64> = Lazy::new(|| env_parse("WS_REPORT_INTERVAL_MS", 1000));
+pub static WS_PING_INTERVAL_MS: Lazy<u64> = Lazy::new(|| env_parse("WS_PING_INTERVAL_MS", 1000));
+// sem nenhuma mensagem (dados ou pong) por esse tempo a conexão é considerada morta
+pub static WS_HEARTBEAT_TIMEOUT_MS: Lazy<u64> = Lazy::new(|| env_parse("WS_HEARTBEAT_TIMEOUT_MS", 3000));
+// health mais velho que isso não é confiável, o roteamento entra no modo degradado
+pub static HEALTH_STALE_AFTER_MS: Lazy<u64> = Lazy::new(|| env_parse("HEALTH_STALE_AFTER_MS", 15000));
+// circuit-breaker | primary-only | pause | last-known
+pub static DEGRADED_ROUTING_MODE: Lazy<String> = Lazy::new(|| {
+    env::var("DEGRADED_ROUTING_MODE").unwrap_or_else(|_| "circuit-breaker".to_string())
+});
+pub static HEALTH_SNAPSHOT_TTL_MS: Lazy<u64> = Lazy::new(|| env_parse("HEALTH_SNAPSHOT_TTL_MS", *HEALTH_STALE_AFTER_MS));
+// como o health do líder chega nas outras instâncias: websocket | redis
+pub static HEALTH_BROADCASTER_NAME: Lazy<String> = Lazy::new(|| {
+    env::var("HEALTH_BROADCASTER").unwrap_or_else(|_| "websocket".to_string())
+});
+// onde ficam os pagamentos contabilizados: redis | memory (só esta instância, para desenvolvimento)
+pub static PAYMENT_STORE_NAME: Lazy<String> = Lazy::new(|| {
+    env::var("PAYMENT_STORE").unwrap_or_else(|_| "redis".to_string())
+});
+
+#[cfg(test)]
+mod tests {
+    use super::check_processor_names;
+    use crate::domain::entities::ProcessorConfig;
+
+    fn processor(name: &str) -> ProcessorConfig {
+        ProcessorConfig { name: name.to_string(), url: String::new(), priority: 0, fee: 0.0, weight: 1 }
+    }
+
+    #[test]
+    fn reserved_processor_names_are_rejected() {
+        assert!(check_processor_names(&[processor("primary"), processor("secondary")]).is_ok());
+        for name in ["updatedAt", "series", "from"] {
+            assert!(check_processor_names(&[processor("primary"), processor(name)]).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn duplicate_processor_names_are_rejected() {
+        let duplicated = [processor("primary"), processor("secondary"), processor("primary")];
+        assert_eq!(check_processor_names(&duplicated), Err("nome de processador repetido: primary".to_string()));
+    }
+}
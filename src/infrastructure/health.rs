@@ -1,42 +1,154 @@
-use crate::domain::entities::ProcessorDecision;
-use crate::infrastructure::config::{GLOBAL_HEALTH_STATUS, PAYMENT_PROCESSOR_DEFAULT_URL, PAYMENT_PROCESSOR_FALLBACK_URL};
+use crate::domain::entities::{HealthStatusAll, ProcessorConfig, ProcessorDecision};
+use crate::infrastructure::config::{
+    DEGRADED_ROUTING_MODE, GLOBAL_HEALTH_STATUS, HEALTH_CHECK_INTERVAL_MS, HEALTH_RATE_LIMIT_KEY,
+    HEALTH_STALE_AFTER_MS, INSTANCE_ID, PROCESSORS, PROCESSOR_TIMEOUT_CEILING_MS, PROCESSOR_TIMEOUT_FLOOR_MS,
+};
+use crate::infrastructure::circuit_breaker::circuit_breaker;
+use crate::infrastructure::leader::is_leader;
+use crate::infrastructure::sync::store_snapshot;
+use crate::infrastructure::stats::{processor_stats, request_timeout};
+use crate::infrastructure::routing::ROUTING_STRATEGY;
+use crate::infrastructure::utils::now_unix_ms;
 use crate::HealthResponse;
+use once_cell::sync::Lazy;
+use redis::aio::ConnectionManager;
+use redis::RedisResult;
 use reqwest::Client;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
 
-pub fn start_service_health() {
+// o que fazer quando o health sincronizado ficou velho demais (líder caiu, WebSocket fora...)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DegradedMode {
+    // ignora o health e confia só nos circuit breakers locais
+    CircuitBreaker,
+    // manda tudo para o processador de maior prioridade
+    PrimaryOnly,
+    // para de processar até o health voltar
+    Pause,
+    // continua usando o último health conhecido
+    LastKnown,
+}
+
+impl DegradedMode {
+    pub fn parse(name: &str) -> Option<DegradedMode> {
+        match name {
+            "circuit-breaker" => Some(DegradedMode::CircuitBreaker),
+            "primary-only" => Some(DegradedMode::PrimaryOnly),
+            "pause" => Some(DegradedMode::Pause),
+            "last-known" => Some(DegradedMode::LastKnown),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DegradedMode::CircuitBreaker => "circuit-breaker",
+            DegradedMode::PrimaryOnly => "primary-only",
+            DegradedMode::Pause => "pause",
+            DegradedMode::LastKnown => "last-known",
+        }
+    }
+}
+
+pub static DEGRADED_MODE: Lazy<DegradedMode> = Lazy::new(|| {
+    DegradedMode::parse(DEGRADED_ROUTING_MODE.as_str()).unwrap_or_else(|| {
+        eprintln!("[HEALTH] Modo degradado desconhecido {}, usando circuit-breaker", DEGRADED_ROUTING_MODE.as_str());
+        DegradedMode::CircuitBreaker
+    })
+});
+
+// mudança observada pelo health check, repassada na hora para quem estiver conectado no líder
+#[derive(Debug, Clone)]
+pub struct HealthEvent {
+    pub processor: String,
+    pub health: HealthResponse,
+    pub updated_at: u64,
+}
+
+static HEALTH_EVENTS: Lazy<broadcast::Sender<HealthEvent>> = Lazy::new(|| broadcast::channel(64).0);
+
+pub fn subscribe_health_events() -> broadcast::Receiver<HealthEvent> {
+    HEALTH_EVENTS.subscribe()
+}
+
+// todas as instâncias rodam o loop, mas só o líder chama os processadores.
+// sem redis (modo standalone) o limite de chamadas é local e não tem snapshot
+pub fn start_service_health(conn: Option<ConnectionManager>) {
     let client = Client::builder()
         .timeout(std::time::Duration::from_millis(100))
         .build()
         .expect("failed to build health check client");
 
     tokio::spawn(async move {
+        let mut conn = conn;
+        let mut local_slots: HashMap<String, Instant> = HashMap::new();
         loop {
-            check_health(&client, PAYMENT_PROCESSOR_DEFAULT_URL.to_string(), true).await;
-            check_health(&client, PAYMENT_PROCESSOR_FALLBACK_URL.to_string(), false).await;
+            if is_leader() {
+                let mut checked = false;
+                for processor in PROCESSORS.iter() {
+                    let slot = match conn.as_mut() {
+                        Some(conn) => acquire_health_slot(conn, &processor.name).await,
+                        None => Ok(acquire_local_health_slot(&mut local_slots, &processor.name)),
+                    };
+                    match slot {
+                        Ok(true) => checked |= check_health(&client, processor).await,
+                        Ok(false) => {}
+                        Err(e) => eprintln!("[HEALTH] Erro ao reservar health check de {}: {:?}", processor.name, e),
+                    }
+                }
+                if let (true, Some(conn)) = (checked, conn.as_mut()) {
+                    if let Err(e) = store_snapshot(conn).await {
+                        eprintln!("[HEALTH] Erro ao salvar snapshot do health: {:?}", e);
+                    }
+                }
+            }
 
-            tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
         }
     });
 }
 
-async fn check_health(client: &Client, base_url: String, is_default: bool) {
+// limite de chamadas vale para o cluster: se o líder mudar no meio do intervalo,
+// o novo só chama quando a janela do anterior expirar
+async fn acquire_health_slot(conn: &mut ConnectionManager, processor: &str) -> RedisResult<bool> {
+    let acquired: Option<String> = redis::cmd("SET")
+        .arg(format!("{}:{}", HEALTH_RATE_LIMIT_KEY, processor))
+        .arg(INSTANCE_ID.as_str())
+        .arg("NX")
+        .arg("PX")
+        .arg(*HEALTH_CHECK_INTERVAL_MS)
+        .query_async(conn)
+        .await?;
+    Ok(acquired.is_some())
+}
+
+fn acquire_local_health_slot(slots: &mut HashMap<String, Instant>, processor: &str) -> bool {
+    let interval = Duration::from_millis(*HEALTH_CHECK_INTERVAL_MS);
+    match slots.get(processor) {
+        Some(last) if last.elapsed() < interval => false,
+        _ => {
+            slots.insert(processor.to_string(), Instant::now());
+            true
+        }
+    }
+}
+
+// retorna se o health foi atualizado
+async fn check_health(client: &Client, processor_config: &ProcessorConfig) -> bool {
+    let base_url = &processor_config.url;
     let url = format!("{}/payments/service-health", base_url);
     match client.get(&url).send().await {
         Ok(response) => {
             match response.json::<HealthResponse>().await {
                 Ok(json) => {
                     let mut guard = GLOBAL_HEALTH_STATUS.write().await;
-                    /*if is_default {
-                        guard.default = json;
-                    } else {
-                        guard.fallback = json;
-                    }*/
-                    let processor = if is_default {
-                        &mut guard.default
-                    } else {
-                        &mut guard.fallback
-                    };
+                    let known = guard.processors.contains_key(&processor_config.name);
+                    let processor = guard.processors.entry(processor_config.name.clone()).or_default();
+                    let changed = !known
+                        || processor.failing != json.failing
+                        || processor.min_response_time != json.min_response_time;
 
                     if json.failing {
                         if processor.failing_since.is_none() {
@@ -47,54 +159,83 @@ async fn check_health(client: &Client, base_url: String, is_default: bool) {
                     }
                     processor.failing = json.failing;
                     processor.min_response_time = json.min_response_time;
+                    let health = processor.clone();
+                    guard.updated_at = now_unix_ms();
+                    let updated_at = guard.updated_at;
+                    drop(guard);
+                    circuit_breaker(&processor_config.name).record_health(json.failing);
 
+                    if changed {
+                        // sem ninguém inscrito o send falha, tudo bem
+                        let _ = HEALTH_EVENTS.send(HealthEvent {
+                            processor: processor_config.name.clone(),
+                            health,
+                            updated_at,
+                        });
+                    }
+                    true
+                }
+                Err(e) => {
+                    eprintln!("[HEALTH] Erro ao fazer parsing JSON de {}: {}", base_url, e);
+                    false
                 }
-                Err(e) => eprintln!("[HEALTH] Erro ao fazer parsing JSON de {}: {}", base_url, e),
             }
         }
-        Err(e) => eprintln!("[HEALTH] Falha ao chamar {}: {}", base_url, e),
+        Err(e) => {
+            eprintln!("[HEALTH] Falha ao chamar {}: {}", base_url, e);
+            false
+        }
     }
 }
 
-pub async fn get_best_processor() -> ProcessorDecision {
-    let health = GLOBAL_HEALTH_STATUS.read().await;
-
-    if health.default.failing && health.fallback.failing {
-        return ProcessorDecision::FAILING;
-    }
+pub fn health_is_stale(health: &HealthStatusAll) -> bool {
+    health.is_stale(now_unix_ms(), Duration::from_millis(*HEALTH_STALE_AFTER_MS))
+}
 
-    if health.default.failing {
-        if let Some(start_ms) = health.default.failing_since {
-            if let Some(elapsed) = elapsed_since(start_ms) {
-                if elapsed.as_secs_f32() > 3.0 {
-                    eprintln!("Mais de 3 segundos {:?}",elapsed);
-                    return ProcessorDecision::FALLBACK;
+pub async fn get_best_processor() -> ProcessorDecision {
+    let mut health = GLOBAL_HEALTH_STATUS.read().await.clone();
+    if health_is_stale(&health) {
+        match *DEGRADED_MODE {
+            DegradedMode::Pause => return ProcessorDecision::FAILING,
+            DegradedMode::PrimaryOnly => {
+                return PROCESSORS.first()
+                    .map(|p| ProcessorDecision::PROCESSOR(p.name.clone()))
+                    .unwrap_or(ProcessorDecision::FAILING);
+            }
+            DegradedMode::CircuitBreaker => {
+                for processor_health in health.processors.values_mut() {
+                    processor_health.failing = false;
+                    processor_health.failing_since = None;
                 }
             }
+            DegradedMode::LastKnown => {}
         }
-        //return ProcessorDecision::FAILING;
     }
-
-    if health.fallback.failing {
-        return ProcessorDecision::DEFAULT;
+    // circuito aberto conta como failing para a estratégia, mesmo que o health check ainda não tenha visto
+    for (processor, processor_health) in health.processors.iter_mut() {
+        apply_circuit_breaker(processor, processor_health);
     }
-
-    ProcessorDecision::DEFAULT
+    let stats = processor_stats();
+    ROUTING_STRATEGY.decide(&health, &stats)
 }
 
-
-fn now_unix_ms() -> u64 {
-    SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default()
-        .as_millis() as u64
+fn apply_circuit_breaker(processor: &str, health: &mut HealthResponse) {
+    let breaker = circuit_breaker(processor);
+    if !breaker.allows_traffic() {
+        health.failing = true;
+        if health.failing_since.is_none() {
+            health.failing_since = breaker.opened_at_ms();
+        }
+    }
 }
 
-fn elapsed_since(timestamp_ms: u64) -> Option<std::time::Duration> {
-    let now = now_unix_ms();
-    if now > timestamp_ms {
-        Some(std::time::Duration::from_millis(now - timestamp_ms))
-    } else {
-        None
-    }
+pub async fn processor_timeout(processor: &str) -> Duration {
+    let health = GLOBAL_HEALTH_STATUS.read().await.get(processor).cloned().unwrap_or_default();
+    let stats = processor_stats().get(processor);
+    request_timeout(
+        &health,
+        &stats,
+        Duration::from_millis(*PROCESSOR_TIMEOUT_FLOOR_MS),
+        Duration::from_millis(*PROCESSOR_TIMEOUT_CEILING_MS),
+    )
 }
@@ -1,10 +1,138 @@
 use std::sync::Arc;
-use reqwest::{Client, Response};
+use std::time::Duration;
+use reqwest::{Client, Response, StatusCode};
 use serde_json::Value;
+use crate::domain::entities::{PaymentVerification, ProcessorOutcome, ProcessorPayment};
 
-pub async fn payments_request(client: &Arc<Client>, host: String, payload: &Value) -> Result<Response, reqwest::Error> {
+pub async fn payments_request(client: &Arc<Client>, host: String, payload: &Value, timeout: Duration) -> Result<Response, reqwest::Error> {
     client
         .post(format!("{}/payments", host))
         .json(&payload)
+        .timeout(timeout)
         .send().await
 }
+
+pub async fn payment_lookup_request(client: &Arc<Client>, host: String, correlation_id: &str, timeout: Duration) -> Result<Response, reqwest::Error> {
+    client
+        .get(format!("{}/payments/{}", host, correlation_id))
+        .timeout(timeout)
+        .send().await
+}
+
+// pergunta ao processador se ele tem o pagamento, usado quando o envio ficou sem resposta
+pub async fn verify_payment(client: &Arc<Client>, host: String, correlation_id: &str, timeout: Duration) -> PaymentVerification {
+    match payment_lookup_request(client, host, correlation_id, timeout).await {
+        Ok(response) if response.status().is_success() => {
+            match response.json::<ProcessorPayment>().await {
+                Ok(payment) => PaymentVerification::Found(payment),
+                Err(e) => PaymentVerification::Unknown(e.to_string()),
+            }
+        }
+        Ok(response) if response.status() == StatusCode::NOT_FOUND => PaymentVerification::NotFound,
+        Ok(response) => PaymentVerification::Unknown(format!("status {}", response.status())),
+        Err(e) => PaymentVerification::Unknown(e.to_string()),
+    }
+}
+
+pub async fn classify_response(result: Result<Response, reqwest::Error>) -> (ProcessorOutcome, String) {
+    match result {
+        Ok(response) => {
+            let status = response.status();
+            let outcome = match status {
+                s if s.is_success() => ProcessorOutcome::Success,
+                StatusCode::CONFLICT => ProcessorOutcome::Duplicate,
+                StatusCode::UNPROCESSABLE_ENTITY => {
+                    let body = response.text().await.unwrap_or_default().to_lowercase();
+                    if body.contains("already") || body.contains("exist") || body.contains("duplicate") {
+                        ProcessorOutcome::Duplicate
+                    } else {
+                        ProcessorOutcome::PermanentRejection
+                    }
+                }
+                StatusCode::REQUEST_TIMEOUT | StatusCode::TOO_MANY_REQUESTS => ProcessorOutcome::TransientFailure,
+                s if s.is_server_error() => ProcessorOutcome::TransientFailure,
+                _ => ProcessorOutcome::PermanentRejection,
+            };
+            (outcome, format!("status {}", status))
+        }
+        Err(e) => (classify_error(&e), e.to_string()),
+    }
+}
+
+// só é timeout quando o reqwest diz que foi; o resto depois do envio vira response_error
+fn classify_error(e: &reqwest::Error) -> ProcessorOutcome {
+    if e.is_connect() || e.is_builder() {
+        ProcessorOutcome::ConnectionError
+    } else if e.is_timeout() {
+        ProcessorOutcome::Timeout
+    } else {
+        ProcessorOutcome::ResponseError
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{classify_response, payments_request};
+    use crate::domain::entities::ProcessorOutcome;
+    use reqwest::Client;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    // servidor de uma conexão só: lê o pedido e responde `reply` (None = fica calado)
+    async fn server(reply: Option<&'static [u8]>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = stream.read(&mut buf).await;
+            match reply {
+                Some(reply) => {
+                    let _ = stream.write_all(reply).await;
+                }
+                None => tokio::time::sleep(Duration::from_secs(5)).await,
+            }
+        });
+        format!("http://{}", addr)
+    }
+
+    async fn outcome(host: String) -> ProcessorOutcome {
+        let client = Arc::new(Client::new());
+        let payload = serde_json::json!({ "correlationId": "a" });
+        let result = payments_request(&client, host, &payload, Duration::from_millis(100)).await;
+        classify_response(result).await.0
+    }
+
+    #[tokio::test]
+    async fn no_answer_in_time_is_a_timeout() {
+        assert_eq!(outcome(server(None).await).await, ProcessorOutcome::Timeout);
+    }
+
+    #[tokio::test]
+    async fn refused_connection_is_a_connection_error() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let host = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        assert_eq!(outcome(host).await, ProcessorOutcome::ConnectionError);
+    }
+
+    #[tokio::test]
+    async fn broken_response_is_not_a_timeout() {
+        assert_eq!(outcome(server(Some(b"isso nao e http\r\n\r\n")).await).await, ProcessorOutcome::ResponseError);
+    }
+
+    #[tokio::test]
+    async fn status_codes_are_classified() {
+        let cases: [(&'static [u8], ProcessorOutcome); 4] = [
+            (b"HTTP/1.1 200 OK\r\ncontent-length: 0\r\n\r\n", ProcessorOutcome::Success),
+            (b"HTTP/1.1 500 Internal Server Error\r\ncontent-length: 0\r\n\r\n", ProcessorOutcome::TransientFailure),
+            (b"HTTP/1.1 422 Unprocessable Entity\r\ncontent-length: 7\r\n\r\ninvalid", ProcessorOutcome::PermanentRejection),
+            (b"HTTP/1.1 422 Unprocessable Entity\r\ncontent-length: 14\r\n\r\nalready exists", ProcessorOutcome::Duplicate),
+        ];
+        for (reply, expected) in cases {
+            assert_eq!(outcome(server(Some(reply)).await).await, expected);
+        }
+    }
+}
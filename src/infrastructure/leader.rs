@@ -0,0 +1,182 @@
+use crate::infrastructure::config::{
+    INSTANCE_ID, INSTANCE_KEY, LEADER_KEY, LEADER_LEASE_MS, LEADER_RENEW_MS, PROCESS_TOKEN, WS_ADVERTISE_URL,
+};
+use once_cell::sync::Lazy;
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, RedisResult, Script};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+static IS_LEADER: AtomicBool = AtomicBool::new(false);
+// sem redis não tem disputa pelo id; com redis vira false se outro processo assumir o INSTANCE_ID
+static OWNS_INSTANCE_ID: AtomicBool = AtomicBool::new(true);
+
+// pega a chave se estiver livre ou renova se o valor for o nosso
+static ACQUIRE_SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(r#"
+local current = redis.call('GET', KEYS[1])
+if current == ARGV[1] then
+    redis.call('PEXPIRE', KEYS[1], ARGV[2])
+    return 1
+end
+if not current then
+    redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+    return 1
+end
+return 0
+"#));
+
+// dono do lease (token do processo) e o endereço WebSocket dele, assim quem não é líder já sabe onde conectar
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lease {
+    pub owner: String,
+    pub url: String,
+}
+
+impl Lease {
+    fn own() -> Lease {
+        Lease { owner: PROCESS_TOKEN.clone(), url: WS_ADVERTISE_URL.clone() }
+    }
+
+    fn encode(&self) -> String {
+        format!("{}|{}", self.owner, self.url)
+    }
+
+    fn decode(value: &str) -> Option<Lease> {
+        let (owner, url) = value.split_once('|')?;
+        Some(Lease { owner: owner.to_string(), url: url.to_string() })
+    }
+
+    pub fn is_own(&self) -> bool {
+        self.owner == *PROCESS_TOKEN
+    }
+}
+
+async fn try_acquire(conn: &mut ConnectionManager, key: &str, value: &str) -> RedisResult<bool> {
+    let acquired: i32 = ACQUIRE_SCRIPT
+        .key(key)
+        .arg(value)
+        .arg(*LEADER_LEASE_MS)
+        .invoke_async(conn)
+        .await?;
+    Ok(acquired == 1)
+}
+
+pub fn is_leader() -> bool {
+    IS_LEADER.load(Ordering::Relaxed)
+}
+
+// pega o lease se estiver livre ou renova se já for nosso
+pub async fn try_acquire_leadership(conn: &mut ConnectionManager) -> RedisResult<bool> {
+    try_acquire(conn, LEADER_KEY, &Lease::own().encode()).await
+}
+
+pub async fn current_leader(conn: &mut ConnectionManager) -> RedisResult<Option<Lease>> {
+    let value: Option<String> = conn.get(LEADER_KEY).await?;
+    Ok(value.as_deref().and_then(Lease::decode))
+}
+
+fn instance_key() -> String {
+    format!("{}:{}", INSTANCE_KEY, INSTANCE_ID.as_str())
+}
+
+// a lista de processamento da fila é por INSTANCE_ID: dois processos com o mesmo id devolveriam
+// um os itens do outro no restart. Espera o lease de um processo anterior (crash) expirar e
+// recusa subir se outro processo vivo continua com o id
+pub async fn claim_instance_id(conn: &mut ConnectionManager) -> Result<(), String> {
+    let deadline = Instant::now() + Duration::from_millis(*LEADER_LEASE_MS * 2);
+    loop {
+        match try_acquire(conn, &instance_key(), PROCESS_TOKEN.as_str()).await {
+            Ok(true) => break,
+            Ok(false) if Instant::now() < deadline => {}
+            Ok(false) => {
+                return Err(format!("INSTANCE_ID {} já está em uso por outro processo, defina um INSTANCE_ID único", INSTANCE_ID.as_str()));
+            }
+            Err(e) => return Err(format!("erro ao reservar o INSTANCE_ID {}: {:?}", INSTANCE_ID.as_str(), e)),
+        }
+        tokio::time::sleep(Duration::from_millis(*LEADER_RENEW_MS)).await;
+    }
+
+    let mut conn = conn.clone();
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_millis(*LEADER_RENEW_MS)).await;
+            match try_acquire(&mut conn, &instance_key(), PROCESS_TOKEN.as_str()).await {
+                Ok(true) => {}
+                // a lista de processamento agora é do outro processo: os workers param de pegar itens
+                Ok(false) => {
+                    OWNS_INSTANCE_ID.store(false, Ordering::Relaxed);
+                    eprintln!("[INSTANCE] INSTANCE_ID {} foi assumido por outro processo, workers parados", INSTANCE_ID.as_str());
+                    break;
+                }
+                Err(e) => eprintln!("[INSTANCE] Erro ao renovar o INSTANCE_ID: {:?}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+// checado pelos workers antes de cada item
+pub fn owns_instance_id() -> bool {
+    OWNS_INSTANCE_ID.load(Ordering::Relaxed)
+}
+
+// sem redis não tem com quem disputar o lease: a instância é sempre líder
+pub fn run_standalone() {
+    IS_LEADER.store(true, Ordering::Relaxed);
+    println!("[LEADER] Sem redis, instância assume os health checks sozinha");
+}
+
+pub fn start_leader_election(conn: ConnectionManager) {
+    tokio::spawn(async move {
+        let mut conn = conn;
+        let mut last_renewed: Option<Instant> = None;
+        loop {
+            let leader = match try_acquire_leadership(&mut conn).await {
+                Ok(true) => {
+                    last_renewed = Some(Instant::now());
+                    true
+                }
+                Ok(false) => false,
+                // sem redis não dá pra renovar: continua líder só até o lease expirar
+                Err(e) => {
+                    eprintln!("[LEADER] Erro ao renovar lease: {:?}", e);
+                    last_renewed.is_some_and(|at| at.elapsed() < Duration::from_millis(*LEADER_LEASE_MS))
+                }
+            };
+            if leader != IS_LEADER.swap(leader, Ordering::Relaxed) {
+                if leader {
+                    println!("[LEADER] Assumiu a liderança dos health checks ({})", WS_ADVERTISE_URL.as_str());
+                } else {
+                    println!("[LEADER] Perdeu a liderança dos health checks");
+                }
+            }
+            if !leader {
+                last_renewed = None;
+            }
+            tokio::time::sleep(Duration::from_millis(*LEADER_RENEW_MS)).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Lease;
+
+    #[test]
+    fn lease_round_trips_owner_and_url() {
+        let own = Lease::own();
+        assert_eq!(Lease::decode(&own.encode()), Some(own.clone()));
+        assert!(own.is_own());
+    }
+
+    #[test]
+    fn lease_of_another_process_with_the_same_url_is_not_ours() {
+        let other = Lease { owner: "0".repeat(32), url: Lease::own().url };
+        assert!(!Lease::decode(&other.encode()).unwrap().is_own());
+    }
+
+    #[test]
+    fn lease_without_owner_is_ignored() {
+        assert_eq!(Lease::decode("ws://local:9001"), None);
+    }
+}
@@ -0,0 +1,511 @@
+use crate::domain::entities::{DeadLetter, PaymentRecord, QueuedPayment};
+use crate::domain::money::Money;
+use crate::domain::queue::PaymentQueue;
+use crate::domain::store::{PaymentStore, StoreResult, SummarySeries};
+use crate::infrastructure::utils::now_unix_ms;
+use futures::future::BoxFuture;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+// tudo aqui é só desta instância e some ao reiniciar: para rodar sem redis em desenvolvimento e nos testes
+
+#[derive(Default)]
+struct StoreData {
+    records: HashMap<String, PaymentRecord>,
+    // por processador: horário -> correlationIds, para as consultas por intervalo
+    history: HashMap<String, BTreeMap<i64, Vec<String>>>,
+    pins: HashMap<String, String>,
+}
+
+impl StoreData {
+    fn in_range(&self, processor: &str, from: i64, to: i64) -> impl Iterator<Item = &PaymentRecord> {
+        self.history.get(processor)
+            .filter(|_| from <= to)
+            .into_iter()
+            .flat_map(move |history| history.range(from..=to))
+            .flat_map(|(_, ids)| ids.iter())
+            .filter_map(|id| self.records.get(id))
+    }
+}
+
+#[derive(Default)]
+pub struct MemoryPaymentStore {
+    data: Mutex<StoreData>,
+}
+
+impl MemoryPaymentStore {
+    pub fn new() -> MemoryPaymentStore {
+        MemoryPaymentStore::default()
+    }
+}
+
+impl PaymentStore for MemoryPaymentStore {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn record<'a>(&'a self, processor: &'a str, id: &'a str, amount: Money, timestamp_ms: i64) -> BoxFuture<'a, StoreResult<bool>> {
+        let mut data = self.data.lock().unwrap();
+        data.pins.remove(id);
+        let stored = !data.records.contains_key(id);
+        if stored {
+            data.records.insert(id.to_string(), PaymentRecord {
+                correlation_id: id.to_string(),
+                processor: processor.to_string(),
+                amount,
+                requested_at: timestamp_ms,
+            });
+            data.history.entry(processor.to_string()).or_default()
+                .entry(timestamp_ms).or_default()
+                .push(id.to_string());
+        }
+        Box::pin(async move { Ok(stored) })
+    }
+
+    fn is_recorded<'a>(&'a self, id: &'a str) -> BoxFuture<'a, StoreResult<bool>> {
+        let recorded = self.data.lock().unwrap().records.contains_key(id);
+        Box::pin(async move { Ok(recorded) })
+    }
+
+    fn lookup<'a>(&'a self, id: &'a str) -> BoxFuture<'a, StoreResult<Option<PaymentRecord>>> {
+        let record = self.data.lock().unwrap().records.get(id).cloned();
+        Box::pin(async move { Ok(record) })
+    }
+
+    fn query_range<'a>(&'a self, processor: &'a str, from: i64, to: i64) -> BoxFuture<'a, StoreResult<Vec<PaymentRecord>>> {
+        let records = self.data.lock().unwrap().in_range(processor, from, to).cloned().collect();
+        Box::pin(async move { Ok(records) })
+    }
+
+    fn summary<'a>(&'a self, processors: &'a [&'a str], from: i64, to: i64) -> BoxFuture<'a, StoreResult<Vec<(u64, Money)>>> {
+        let data = self.data.lock().unwrap();
+        let totals = processors.iter()
+            .map(|processor| data.in_range(processor, from, to)
+                .fold((0, Money::ZERO), |(count, total), record| (count + 1, total + record.amount)))
+            .collect();
+        Box::pin(async move { Ok(totals) })
+    }
+
+    fn series<'a>(&'a self, processors: &'a [&'a str], from: i64, to: i64, interval: i64) -> BoxFuture<'a, StoreResult<SummarySeries>> {
+        let data = self.data.lock().unwrap();
+        let mut series = SummarySeries::new();
+        if interval > 0 {
+            for (index, processor) in processors.iter().enumerate() {
+                for record in data.in_range(processor, from, to) {
+                    let slot = record.requested_at.div_euclid(interval) * interval;
+                    let totals = series.entry(slot).or_insert_with(|| vec![(0, Money::ZERO); processors.len()]);
+                    totals[index].0 += 1;
+                    totals[index].1 += record.amount;
+                }
+            }
+        }
+        Box::pin(async move { Ok(series) })
+    }
+
+    fn pin_processor<'a>(&'a self, id: &'a str, processor: &'a str) -> BoxFuture<'a, StoreResult<String>> {
+        let pinned = self.data.lock().unwrap().pins.entry(id.to_string()).or_insert_with(|| processor.to_string()).clone();
+        Box::pin(async move { Ok(pinned) })
+    }
+
+    fn release_processor<'a>(&'a self, id: &'a str, processor: &'a str) -> BoxFuture<'a, StoreResult<()>> {
+        let mut data = self.data.lock().unwrap();
+        if data.pins.get(id).is_some_and(|pinned| pinned == processor) {
+            data.pins.remove(id);
+        }
+        Box::pin(async move { Ok(()) })
+    }
+
+    fn purge<'a>(&'a self, processors: &'a [&'a str]) -> BoxFuture<'a, StoreResult<()>> {
+        let mut data = self.data.lock().unwrap();
+        data.records.retain(|_, record| !processors.contains(&record.processor.as_str()));
+        for processor in processors {
+            data.history.remove(*processor);
+        }
+        data.pins.clear();
+        Box::pin(async move { Ok(()) })
+    }
+}
+
+#[derive(Default)]
+struct QueueData {
+    accepted: HashSet<String>,
+    // entra no fim e sai do começo; o que é recuperado de processamento volta para o começo
+    pending: VecDeque<Vec<u8>>,
+    processing: Vec<Vec<u8>>,
+    // (quando pode voltar, ordem de chegada) -> item
+    delayed: BTreeMap<(u64, u64), Vec<u8>>,
+    delayed_seq: u64,
+    failed: BTreeMap<String, DeadLetter>,
+}
+
+impl QueueData {
+    fn take_processing(&mut self, claimed: &[u8]) {
+        if let Some(position) = self.processing.iter().position(|item| item == claimed) {
+            self.processing.remove(position);
+        }
+    }
+}
+
+#[derive(Default)]
+struct QueueShared {
+    data: Mutex<QueueData>,
+    // acorda um worker parado no claim quando chega item novo
+    ready: Notify,
+}
+
+// os handles dos workers dividem o mesmo estado
+#[derive(Default, Clone)]
+pub struct MemoryPaymentQueue {
+    shared: Arc<QueueShared>,
+}
+
+impl MemoryPaymentQueue {
+    pub fn new() -> MemoryPaymentQueue {
+        MemoryPaymentQueue::default()
+    }
+
+    fn push(&self, payload: Vec<u8>) {
+        self.shared.data.lock().unwrap().pending.push_back(payload);
+        self.shared.ready.notify_one();
+    }
+}
+
+impl PaymentQueue for MemoryPaymentQueue {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    fn for_worker(&self) -> BoxFuture<'_, StoreResult<Box<dyn PaymentQueue>>> {
+        let queue = self.clone();
+        Box::pin(async move { Ok(Box::new(queue) as Box<dyn PaymentQueue>) })
+    }
+
+    fn enqueue<'a>(&'a self, correlation_id: &'a str, payload: &'a [u8]) -> BoxFuture<'a, StoreResult<bool>> {
+        let accepted = self.shared.data.lock().unwrap().accepted.insert(correlation_id.to_string());
+        if accepted {
+            self.push(payload.to_vec());
+        }
+        Box::pin(async move { Ok(accepted) })
+    }
+
+    fn purge_accepted(&self) -> BoxFuture<'_, StoreResult<()>> {
+        self.shared.data.lock().unwrap().accepted.clear();
+        Box::pin(async move { Ok(()) })
+    }
+
+    fn claim(&self, timeout: Duration) -> BoxFuture<'_, StoreResult<Option<Vec<u8>>>> {
+        Box::pin(async move {
+            let deadline = Instant::now() + timeout;
+            loop {
+                // registra a espera antes de olhar a fila, senão um push entre as duas coisas se perde
+                let ready = self.shared.ready.notified();
+                tokio::pin!(ready);
+                ready.as_mut().enable();
+                {
+                    let mut data = self.shared.data.lock().unwrap();
+                    if let Some(item) = data.pending.pop_front() {
+                        data.processing.push(item.clone());
+                        return Ok(Some(item));
+                    }
+                }
+                if tokio::time::timeout_at(deadline, ready).await.is_err() {
+                    return Ok(None);
+                }
+            }
+        })
+    }
+
+    fn ack<'a>(&'a self, claimed: &'a [u8]) -> BoxFuture<'a, StoreResult<()>> {
+        self.shared.data.lock().unwrap().take_processing(claimed);
+        Box::pin(async move { Ok(()) })
+    }
+
+    fn in_flight(&self) -> BoxFuture<'_, StoreResult<u64>> {
+        let in_flight = self.shared.data.lock().unwrap().processing.len() as u64;
+        Box::pin(async move { Ok(in_flight) })
+    }
+
+    fn recover_in_flight(&self) -> BoxFuture<'_, StoreResult<usize>> {
+        let recovered = {
+            let mut data = self.shared.data.lock().unwrap();
+            let processing = std::mem::take(&mut data.processing);
+            let recovered = processing.len();
+            for item in processing.into_iter().rev() {
+                data.pending.push_front(item);
+            }
+            recovered
+        };
+        for _ in 0..recovered {
+            self.shared.ready.notify_one();
+        }
+        Box::pin(async move { Ok(recovered) })
+    }
+
+    fn schedule_retry<'a>(&'a self, claimed: &'a [u8], payload: &'a [u8], due_ms: u64) -> BoxFuture<'a, StoreResult<()>> {
+        let mut data = self.shared.data.lock().unwrap();
+        data.take_processing(claimed);
+        data.delayed_seq += 1;
+        let key = (due_ms, data.delayed_seq);
+        data.delayed.insert(key, payload.to_vec());
+        Box::pin(async move { Ok(()) })
+    }
+
+    fn promote_due(&self, now_ms: u64, limit: usize) -> BoxFuture<'_, StoreResult<usize>> {
+        let due: Vec<Vec<u8>> = {
+            let mut data = self.shared.data.lock().unwrap();
+            let keys: Vec<(u64, u64)> = data.delayed.range(..=(now_ms, u64::MAX)).map(|(key, _)| *key).take(limit).collect();
+            keys.iter().filter_map(|key| data.delayed.remove(key)).collect()
+        };
+        let promoted = due.len();
+        for item in due {
+            self.push(item);
+        }
+        Box::pin(async move { Ok(promoted) })
+    }
+
+    fn dead_letter<'a>(&'a self, claimed: &'a [u8], entry: &'a DeadLetter) -> BoxFuture<'a, StoreResult<()>> {
+        let mut data = self.shared.data.lock().unwrap();
+        data.failed.insert(entry.payment.correlation_id.clone(), entry.clone());
+        data.take_processing(claimed);
+        Box::pin(async move { Ok(()) })
+    }
+
+    fn list_dead_letters(&self) -> BoxFuture<'_, StoreResult<Vec<DeadLetter>>> {
+        let entries = self.shared.data.lock().unwrap().failed.values().cloned().collect();
+        Box::pin(async move { Ok(entries) })
+    }
+
+    fn get_dead_letter<'a>(&'a self, correlation_id: &'a str) -> BoxFuture<'a, StoreResult<Option<DeadLetter>>> {
+        let entry = self.shared.data.lock().unwrap().failed.get(correlation_id).cloned();
+        Box::pin(async move { Ok(entry) })
+    }
+
+    fn replay_dead_letter(&self, entry: DeadLetter) -> BoxFuture<'_, StoreResult<bool>> {
+        Box::pin(async move {
+            let removed = self.shared.data.lock().unwrap().failed.remove(&entry.payment.correlation_id).is_some();
+            if removed {
+                let payload = serde_json::to_vec(&QueuedPayment::replay(entry, now_unix_ms())).map_err(|e| e.to_string())?;
+                self.push(payload);
+            }
+            Ok(removed)
+        })
+    }
+
+    fn discard_dead_letter<'a>(&'a self, correlation_id: &'a str) -> BoxFuture<'a, StoreResult<bool>> {
+        let removed = self.shared.data.lock().unwrap().failed.remove(correlation_id).is_some();
+        Box::pin(async move { Ok(removed) })
+    }
+
+    fn discard_all_dead_letters(&self) -> BoxFuture<'_, StoreResult<usize>> {
+        let discarded = std::mem::take(&mut self.shared.data.lock().unwrap().failed).len();
+        Box::pin(async move { Ok(discarded) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MemoryPaymentQueue, MemoryPaymentStore};
+    use crate::domain::entities::{DeadLetter, PostPayments, QueuedPayment};
+    use crate::domain::money::Money;
+    use crate::domain::queue::PaymentQueue;
+    use crate::domain::store::PaymentStore;
+    use std::time::Duration;
+
+    const PROCESSORS: [&str; 2] = ["default", "fallback"];
+
+    fn dead_letter(id: &str) -> DeadLetter {
+        DeadLetter {
+            payment: PostPayments { correlation_id: id.to_string(), amount: Money::from_cents(1990) },
+            reason: "tentativas esgotadas".to_string(),
+            attempts: 3,
+            last_processor: Some("default".to_string()),
+            failed_at: "2025-07-01T12:00:00.000Z".to_string(),
+            requested_at: 1_000,
+            history: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn records_each_payment_once() {
+        let store = MemoryPaymentStore::new();
+        assert!(store.record("default", "a", Money::from_cents(1000), 1_000).await.unwrap());
+        assert!(!store.record("fallback", "a", Money::from_cents(1000), 2_000).await.unwrap());
+        assert!(store.is_recorded("a").await.unwrap());
+        assert!(!store.is_recorded("b").await.unwrap());
+
+        let record = store.lookup("a").await.unwrap().unwrap();
+        assert_eq!(record.processor, "default");
+        assert_eq!(record.requested_at, 1_000);
+        assert_eq!(store.lookup("b").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn query_range_is_inclusive_and_ordered() {
+        let store = MemoryPaymentStore::new();
+        store.record("default", "c", Money::from_cents(300), 3_000).await.unwrap();
+        store.record("default", "a", Money::from_cents(100), 1_000).await.unwrap();
+        store.record("default", "b", Money::from_cents(200), 2_000).await.unwrap();
+        store.record("fallback", "d", Money::from_cents(400), 2_000).await.unwrap();
+
+        let ids = |records: Vec<crate::domain::entities::PaymentRecord>| {
+            records.into_iter().map(|r| r.correlation_id).collect::<Vec<_>>()
+        };
+        assert_eq!(ids(store.query_range("default", 1_000, 3_000).await.unwrap()), ["a", "b", "c"]);
+        assert_eq!(ids(store.query_range("default", 1_001, 2_999).await.unwrap()), ["b"]);
+        assert_eq!(ids(store.query_range("fallback", 0, 10_000).await.unwrap()), ["d"]);
+        assert!(store.query_range("default", 3_000, 1_000).await.unwrap().is_empty());
+        assert!(store.query_range("unknown", 0, 10_000).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn summary_and_series_per_processor() {
+        let store = MemoryPaymentStore::new();
+        store.record("default", "a", Money::from_cents(100), 1_000).await.unwrap();
+        store.record("default", "b", Money::from_cents(250), 1_500).await.unwrap();
+        store.record("fallback", "c", Money::from_cents(400), 2_200).await.unwrap();
+
+        let totals = store.summary(&PROCESSORS, 0, 10_000).await.unwrap();
+        assert_eq!(totals, vec![(2, Money::from_cents(350)), (1, Money::from_cents(400))]);
+        let totals = store.summary(&PROCESSORS, 1_500, 2_000).await.unwrap();
+        assert_eq!(totals, vec![(1, Money::from_cents(250)), (0, Money::ZERO)]);
+
+        let series = store.series(&PROCESSORS, 0, 10_000, 1_000).await.unwrap();
+        assert_eq!(series.len(), 2);
+        assert_eq!(series[&1_000], vec![(2, Money::from_cents(350)), (0, Money::ZERO)]);
+        assert_eq!(series[&2_000], vec![(0, Money::ZERO), (1, Money::from_cents(400))]);
+        assert!(store.series(&PROCESSORS, 0, 10_000, 0).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn pin_keeps_the_first_processor_until_released_or_recorded() {
+        let store = MemoryPaymentStore::new();
+        assert_eq!(store.pin_processor("a", "default").await.unwrap(), "default");
+        assert_eq!(store.pin_processor("a", "fallback").await.unwrap(), "default");
+
+        // liberar por outro processador não mexe no pin
+        store.release_processor("a", "fallback").await.unwrap();
+        assert_eq!(store.pin_processor("a", "fallback").await.unwrap(), "default");
+
+        store.release_processor("a", "default").await.unwrap();
+        assert_eq!(store.pin_processor("a", "fallback").await.unwrap(), "fallback");
+
+        store.record("fallback", "a", Money::from_cents(100), 1_000).await.unwrap();
+        assert_eq!(store.pin_processor("a", "default").await.unwrap(), "default");
+    }
+
+    #[tokio::test]
+    async fn purge_drops_records_and_pins() {
+        let store = MemoryPaymentStore::new();
+        store.record("default", "a", Money::from_cents(100), 1_000).await.unwrap();
+        store.record("fallback", "b", Money::from_cents(100), 1_000).await.unwrap();
+        store.pin_processor("c", "default").await.unwrap();
+
+        store.purge(&["default"]).await.unwrap();
+        assert!(!store.is_recorded("a").await.unwrap());
+        assert!(store.is_recorded("b").await.unwrap());
+        assert_eq!(store.summary(&PROCESSORS, 0, 10_000).await.unwrap()[0], (0, Money::ZERO));
+        assert_eq!(store.pin_processor("c", "fallback").await.unwrap(), "fallback");
+        assert!(store.record("default", "a", Money::from_cents(100), 1_000).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn enqueue_accepts_each_correlation_id_once() {
+        let queue = MemoryPaymentQueue::new();
+        assert!(queue.enqueue("a", b"first").await.unwrap());
+        assert!(!queue.enqueue("a", b"again").await.unwrap());
+        assert_eq!(queue.claim(Duration::ZERO).await.unwrap(), Some(b"first".to_vec()));
+        assert_eq!(queue.claim(Duration::ZERO).await.unwrap(), None);
+
+        queue.purge_accepted().await.unwrap();
+        assert!(queue.enqueue("a", b"after purge").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn claim_is_fifo_and_tracks_in_flight() {
+        let queue = MemoryPaymentQueue::new();
+        queue.enqueue("a", b"a").await.unwrap();
+        queue.enqueue("b", b"b").await.unwrap();
+
+        let first = queue.claim(Duration::ZERO).await.unwrap().unwrap();
+        let second = queue.claim(Duration::ZERO).await.unwrap().unwrap();
+        assert_eq!((first.as_slice(), second.as_slice()), (&b"a"[..], &b"b"[..]));
+        assert_eq!(queue.in_flight().await.unwrap(), 2);
+
+        queue.ack(&first).await.unwrap();
+        assert_eq!(queue.in_flight().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn claim_waits_for_an_item_or_times_out() {
+        let queue = MemoryPaymentQueue::new();
+        assert_eq!(queue.claim(Duration::from_millis(20)).await.unwrap(), None);
+
+        let worker = queue.clone();
+        let waiting = tokio::spawn(async move { worker.claim(Duration::from_secs(5)).await });
+        tokio::task::yield_now().await;
+        queue.enqueue("a", b"a").await.unwrap();
+        assert_eq!(waiting.await.unwrap().unwrap(), Some(b"a".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn recovered_items_go_back_to_the_front() {
+        let queue = MemoryPaymentQueue::new();
+        queue.enqueue("a", b"a").await.unwrap();
+        queue.enqueue("b", b"b").await.unwrap();
+        queue.claim(Duration::ZERO).await.unwrap();
+        queue.enqueue("c", b"c").await.unwrap();
+
+        assert_eq!(queue.recover_in_flight().await.unwrap(), 1);
+        assert_eq!(queue.in_flight().await.unwrap(), 0);
+        assert_eq!(queue.claim(Duration::ZERO).await.unwrap(), Some(b"a".to_vec()));
+        assert_eq!(queue.claim(Duration::ZERO).await.unwrap(), Some(b"b".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn retries_come_back_only_when_due() {
+        let queue = MemoryPaymentQueue::new();
+        queue.enqueue("a", b"a").await.unwrap();
+        queue.enqueue("b", b"b").await.unwrap();
+        let a = queue.claim(Duration::ZERO).await.unwrap().unwrap();
+        let b = queue.claim(Duration::ZERO).await.unwrap().unwrap();
+        queue.schedule_retry(&a, b"a retry", 2_000).await.unwrap();
+        queue.schedule_retry(&b, b"b retry", 1_000).await.unwrap();
+        assert_eq!(queue.in_flight().await.unwrap(), 0);
+
+        assert_eq!(queue.promote_due(999, 10).await.unwrap(), 0);
+        assert_eq!(queue.promote_due(2_000, 1).await.unwrap(), 1);
+        assert_eq!(queue.claim(Duration::ZERO).await.unwrap(), Some(b"b retry".to_vec()));
+        assert_eq!(queue.promote_due(2_000, 10).await.unwrap(), 1);
+        assert_eq!(queue.claim(Duration::ZERO).await.unwrap(), Some(b"a retry".to_vec()));
+    }
+
+    #[tokio::test]
+    async fn dead_letters_can_be_replayed_once_or_discarded() {
+        let queue = MemoryPaymentQueue::new();
+        queue.enqueue("a", b"a").await.unwrap();
+        let claimed = queue.claim(Duration::ZERO).await.unwrap().unwrap();
+        queue.dead_letter(&claimed, &dead_letter("a")).await.unwrap();
+        queue.dead_letter(&[], &dead_letter("b")).await.unwrap();
+        assert_eq!(queue.in_flight().await.unwrap(), 0);
+        assert_eq!(queue.list_dead_letters().await.unwrap().len(), 2);
+
+        let entry = queue.get_dead_letter("a").await.unwrap().unwrap();
+        assert!(queue.replay_dead_letter(entry.clone()).await.unwrap());
+        assert!(!queue.replay_dead_letter(entry).await.unwrap());
+        let replayed = queue.claim(Duration::ZERO).await.unwrap().unwrap();
+        let replayed: QueuedPayment = serde_json::from_slice(&replayed).unwrap();
+        assert_eq!(replayed.payment.correlation_id, "a");
+        assert_eq!(replayed.attempts, 0);
+        assert_ne!(replayed.enqueued_at, 0);
+        assert_eq!(replayed.requested_at, 1_000);
+
+        assert!(!queue.discard_dead_letter("a").await.unwrap());
+        queue.dead_letter(&[], &dead_letter("c")).await.unwrap();
+        assert!(queue.discard_dead_letter("c").await.unwrap());
+        assert_eq!(queue.discard_all_dead_letters().await.unwrap(), 1);
+        assert!(queue.list_dead_letters().await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,92 @@
+use crate::domain::entities::ProcessorOutcome;
+use crate::infrastructure::utils::now_unix_ms;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+pub type OutcomeCounters = BTreeMap<String, BTreeMap<String, u64>>;
+
+// instância que parou de reportar sai do /admin/metrics depois disso; no pub/sub o líder
+// não fica sabendo quando alguém cai, então não dá pra depender do forget_follower
+const INSTANCE_METRICS_TTL_MS: u64 = 5000;
+
+// o que cada instância conectada reportou para o líder
+#[derive(Serialize, Debug, Clone, Default)]
+pub struct InstanceMetrics {
+    pub outcomes: OutcomeCounters,
+    #[serde(rename = "inFlight")]
+    pub in_flight: Option<u64>,
+    #[serde(rename = "updatedAt")]
+    pub updated_at: u64,
+}
+
+static OUTCOME_COUNTERS: Lazy<Mutex<BTreeMap<String, BTreeMap<&'static str, u64>>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+pub fn record_outcome(processor: &str, outcome: ProcessorOutcome) {
+    let mut counters = OUTCOME_COUNTERS.lock().unwrap();
+    let processor_counters = counters.entry(processor.to_string()).or_insert_with(|| {
+        ProcessorOutcome::ALL.iter().map(|o| (o.as_str(), 0)).collect()
+    });
+    *processor_counters.entry(outcome.as_str()).or_insert(0) += 1;
+}
+
+pub fn outcome_counters() -> BTreeMap<String, BTreeMap<&'static str, u64>> {
+    OUTCOME_COUNTERS.lock().unwrap().clone()
+}
+
+static INSTANCE_METRICS: Lazy<Mutex<BTreeMap<String, InstanceMetrics>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+pub fn local_outcomes() -> OutcomeCounters {
+    outcome_counters().into_iter()
+        .map(|(processor, counters)| (processor, counters.into_iter().map(|(o, c)| (o.to_string(), c)).collect()))
+        .collect()
+}
+
+fn drop_stale(metrics: &mut BTreeMap<String, InstanceMetrics>, now: u64) {
+    metrics.retain(|_, entry| now.saturating_sub(entry.updated_at) <= INSTANCE_METRICS_TTL_MS);
+}
+
+pub fn store_instance_outcomes(instance: &str, outcomes: OutcomeCounters) {
+    let mut metrics = INSTANCE_METRICS.lock().unwrap();
+    drop_stale(&mut metrics, now_unix_ms());
+    let entry = metrics.entry(instance.to_string()).or_default();
+    entry.outcomes = outcomes;
+    entry.updated_at = now_unix_ms();
+}
+
+pub fn store_instance_queue_depth(instance: &str, in_flight: u64) {
+    let mut metrics = INSTANCE_METRICS.lock().unwrap();
+    drop_stale(&mut metrics, now_unix_ms());
+    let entry = metrics.entry(instance.to_string()).or_default();
+    entry.in_flight = Some(in_flight);
+    entry.updated_at = now_unix_ms();
+}
+
+pub fn remove_instance_metrics(instance: &str) {
+    INSTANCE_METRICS.lock().unwrap().remove(instance);
+}
+
+pub fn instance_metrics() -> BTreeMap<String, InstanceMetrics> {
+    let mut metrics = INSTANCE_METRICS.lock().unwrap();
+    drop_stale(&mut metrics, now_unix_ms());
+    metrics.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{drop_stale, InstanceMetrics, INSTANCE_METRICS_TTL_MS};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn instances_that_stopped_reporting_are_dropped() {
+        let now = 100_000;
+        let mut metrics = BTreeMap::from([
+            ("viva".to_string(), InstanceMetrics { updated_at: now - 1_000, ..Default::default() }),
+            ("no limite".to_string(), InstanceMetrics { updated_at: now - INSTANCE_METRICS_TTL_MS, ..Default::default() }),
+            ("caiu".to_string(), InstanceMetrics { updated_at: now - INSTANCE_METRICS_TTL_MS - 1, ..Default::default() }),
+        ]);
+        drop_stale(&mut metrics, now);
+        assert_eq!(metrics.keys().collect::<Vec<_>>(), ["no limite", "viva"]);
+    }
+}
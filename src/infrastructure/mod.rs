@@ -4,19 +4,70 @@ pub mod redis;
 pub mod health;
 pub mod http_clients;
 pub mod ws;
+pub mod queue;
+pub mod metrics;
+pub mod routing;
+pub mod circuit_breaker;
+pub mod stats;
+pub mod leader;
+pub mod ws_auth;
+pub mod sync;
+pub mod pubsub;
+pub mod broadcast;
+pub mod store;
+pub mod memory;
 
-pub use utils::{
-    date_to_ts, round2
+pub use http_clients::{
+    classify_response, payment_lookup_request, payments_request, verify_payment
 };
 
-pub use http_clients::{
-    payments_request
+pub use metrics::{
+    instance_metrics, local_outcomes, outcome_counters, record_outcome, InstanceMetrics, OutcomeCounters
 };
 
 pub use redis::{
-    get_redis_connection,store_summary
+    adopt_bucket_width, get_redis_connection, is_recorded, pin_processor, release_processor, lookup_payment, payments_in_range, purge_payments, store_summary,
+    summary_series, summary_totals
+};
+
+pub use queue::{
+    ack_payment, claim_payment, dead_letter_payment, discard_all_dead_letters, discard_dead_letter,
+    enqueue_payment, get_dead_letter, in_flight_count, list_dead_letters, promote_due, recover_in_flight, replay_dead_letter,
+    schedule_retry, start_delayed_promoter
+};
+
+pub use circuit_breaker::{
+    circuit_breaker, circuit_states, CircuitBreaker, CircuitState
+};
+
+pub use stats::{
+    cluster_report, local_report, processor_stats, record_call, start_stats_refresher, StatsReport, StatsSnapshot
+};
+
+pub use routing::{
+    RoutingStrategy, ROUTING_STRATEGY
+};
+
+pub use leader::{
+    claim_instance_id, current_leader, is_leader, owns_instance_id, run_standalone, start_leader_election, Lease
 };
 
 pub use ws::{
-    run_master,run_slave
+    run_master, run_slave
+};
+
+pub use sync::{
+    bootstrap_from_snapshot, store_snapshot, Envelope, SyncMessage, PROTOCOL_VERSION
+};
+
+pub use broadcast::{
+    broadcaster_from_name, HealthBroadcaster, HEALTH_BROADCASTER
+};
+
+pub use store::{
+    open_backend, Backend, RedisPaymentQueue, RedisPaymentStore
+};
+
+pub use memory::{
+    MemoryPaymentQueue, MemoryPaymentStore
 };
@@ -0,0 +1,103 @@
+use crate::infrastructure::config::{
+    HEALTH_REPORT_CHANNEL, HEALTH_SYNC_CHANNEL, REDIS_URL, WS_REPORT_INTERVAL_MS, WS_SNAPSHOT_INTERVAL_MS,
+};
+use crate::infrastructure::health::subscribe_health_events;
+use crate::infrastructure::leader::is_leader;
+use crate::infrastructure::sync::{
+    apply_follower_frame, apply_leader_frame, delta_frame, reconnect_policy, report_frames, snapshot_frames,
+};
+use futures::StreamExt;
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, RedisResult};
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+
+// mesmo protocolo do WebSocket, só que pelos canais do redis: não precisa de porta extra
+// nem saber o endereço do líder, quem é líder publica e todo mundo assina
+pub fn run_publisher(conn: ConnectionManager) {
+    tokio::spawn(async move {
+        let mut conn = conn;
+        let mut events = subscribe_health_events();
+        let mut snapshot = tokio::time::interval(Duration::from_millis(*WS_SNAPSHOT_INTERVAL_MS));
+        let mut report = tokio::time::interval(Duration::from_millis(*WS_REPORT_INTERVAL_MS));
+        loop {
+            let (channel, frames) = tokio::select! {
+                _ = snapshot.tick() => {
+                    if !is_leader() {
+                        continue;
+                    }
+                    (HEALTH_SYNC_CHANNEL, snapshot_frames().await)
+                }
+                event = events.recv() => match event {
+                    Ok(event) => (HEALTH_SYNC_CHANNEL, vec![delta_frame(event)]),
+                    // perdeu deltas: manda o estado completo
+                    Err(RecvError::Lagged(_)) => (HEALTH_SYNC_CHANNEL, snapshot_frames().await),
+                    Err(RecvError::Closed) => break,
+                },
+                _ = report.tick() => {
+                    if is_leader() {
+                        continue;
+                    }
+                    (HEALTH_REPORT_CHANNEL, report_frames(&mut conn).await)
+                }
+            };
+            for frame in frames {
+                let published: RedisResult<u64> = conn.publish(channel, frame).await;
+                if let Err(e) = published {
+                    eprintln!("[PUBSUB] Erro ao publicar em {}: {:?}", channel, e);
+                    break;
+                }
+            }
+        }
+    });
+}
+
+pub fn run_subscriber() {
+    tokio::spawn(async move {
+        let policy = reconnect_policy();
+        let mut failures: u32 = 0;
+        loop {
+            match subscribe().await {
+                Ok(()) => failures = 0,
+                Err(e) => {
+                    eprintln!("[PUBSUB] Erro na assinatura dos canais de health: {:?}", e);
+                    failures = failures.saturating_add(1);
+                }
+            }
+            tokio::time::sleep(policy.delay(failures.max(1))).await;
+        }
+    });
+}
+
+// o líder só escuta os relatórios, os outros só escutam o health do líder
+async fn subscribe() -> RedisResult<()> {
+    let client = redis::Client::open(REDIS_URL.as_str())?;
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub.subscribe(&[HEALTH_SYNC_CHANNEL, HEALTH_REPORT_CHANNEL]).await?;
+    println!("[PUBSUB] Assinando {} e {}", HEALTH_SYNC_CHANNEL, HEALTH_REPORT_CHANNEL);
+
+    let mut messages = pubsub.on_message();
+    while let Some(msg) = messages.next().await {
+        let frame: String = match msg.get_payload() {
+            Ok(frame) => frame,
+            Err(e) => {
+                eprintln!("[PUBSUB] Payload inválido: {:?}", e);
+                continue;
+            }
+        };
+        match (msg.get_channel_name(), is_leader()) {
+            (HEALTH_SYNC_CHANNEL, false) => {
+                if let Err(err) = apply_leader_frame(&frame).await {
+                    eprintln!("[SYNC] {err}");
+                }
+            }
+            // sem conexão por instância não há como saber quem caiu: as métricas expiram pelo updatedAt
+            (HEALTH_REPORT_CHANNEL, true) => {
+                apply_follower_frame(&frame);
+            }
+            _ => {}
+        }
+    }
+    println!("[PUBSUB] Assinatura encerrada");
+    Ok(())
+}
@@ -0,0 +1,244 @@
+use crate::domain::entities::{DeadLetter, QueuedPayment};
+use crate::domain::queue::PaymentQueue;
+use crate::infrastructure::config::{
+    ACCEPTED_KEY, ACCEPTED_TTL_MS, INSTANCE_ID, INSTANCE_KEY, QUEUE_DELAYED_KEY, QUEUE_FAILED_KEY, QUEUE_KEY,
+    QUEUE_PROCESSING_KEY, RECORDED_KEY,
+};
+use crate::infrastructure::utils::now_unix_ms;
+use once_cell::sync::Lazy;
+use redis::aio::ConnectionManager;
+use redis::{pipe, AsyncCommands, Direction, RedisResult, Script};
+use std::sync::Arc;
+
+// cada instância tem a sua lista de itens em processamento, assim um restart
+// só recupera o que ela mesma tinha pego
+fn processing_key() -> String {
+    format!("{}:{}", QUEUE_PROCESSING_KEY, INSTANCE_ID.as_str())
+}
+
+// o correlationId só entra na fila uma vez, POSTs repetidos não geram outro item. A marca de
+// aceito (KEYS[1]) expira depois do prazo das retentativas; passado isso, o que já foi
+// contabilizado (KEYS[2]) ou está na fila de falhas (KEYS[3]) continua barrado
+static ENQUEUE_SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(r#"
+if redis.call('HEXISTS', KEYS[2], ARGV[1]) == 1 or redis.call('HEXISTS', KEYS[3], ARGV[1]) == 1 then
+    return 0
+end
+if redis.call('SET', KEYS[1], 1, 'NX', 'PX', ARGV[3]) then
+    redis.call('LPUSH', KEYS[4], ARGV[2])
+    return 1
+end
+return 0
+"#));
+
+fn accepted_key(correlation_id: &str) -> String {
+    format!("{}:{}", ACCEPTED_KEY, correlation_id)
+}
+
+pub async fn enqueue_payment(conn: &mut ConnectionManager, correlation_id: &str, payload: &[u8]) -> RedisResult<bool> {
+    let enqueued: i32 = ENQUEUE_SCRIPT
+        .key(accepted_key(correlation_id))
+        .key(RECORDED_KEY)
+        .key(QUEUE_FAILED_KEY)
+        .key(QUEUE_KEY)
+        .arg(correlation_id)
+        .arg(payload)
+        .arg(*ACCEPTED_TTL_MS)
+        .invoke_async(conn)
+        .await?;
+    Ok(enqueued == 1)
+}
+
+async fn scan_keys(conn: &mut ConnectionManager, pattern: &str) -> RedisResult<Vec<String>> {
+    let mut iter = conn.scan_match::<_, String>(pattern).await?;
+    let mut keys = Vec::new();
+    while let Some(key) = iter.next_item().await {
+        keys.push(key);
+    }
+    Ok(keys)
+}
+
+// apaga as marcas de aceito (e o SET único de antes delas)
+pub async fn purge_accepted(conn: &mut ConnectionManager) -> RedisResult<()> {
+    let mut keys = scan_keys(conn, &accepted_key("*")).await?;
+    keys.push(ACCEPTED_KEY.to_string());
+    for chunk in keys.chunks(500) {
+        let _: () = conn.del(chunk).await?;
+    }
+    Ok(())
+}
+
+// BLMOVE bloqueia a conexão, então cada worker precisa da sua própria ConnectionManager
+pub async fn claim_payment(conn: &mut ConnectionManager, timeout_secs: f64) -> RedisResult<Option<Vec<u8>>> {
+    conn.blmove(QUEUE_KEY, processing_key(), Direction::Right, Direction::Left, timeout_secs).await
+}
+
+pub async fn ack_payment(conn: &mut ConnectionManager, payload: &[u8]) -> RedisResult<()> {
+    conn.lrem(processing_key(), 1, payload).await
+}
+
+pub async fn in_flight_count(conn: &mut ConnectionManager) -> RedisResult<u64> {
+    conn.llen(processing_key()).await
+}
+
+// devolve para a fila os itens que ficaram presos em processamento (crash/OOM): os desta instância
+// e os de instâncias que não voltaram (scale down, INSTANCE_ID trocado)
+pub async fn recover_in_flight(conn: &mut ConnectionManager) -> RedisResult<usize> {
+    let key = processing_key();
+    let mut recovered = 0;
+    loop {
+        let moved: Option<Vec<u8>> = conn.lmove(&key, QUEUE_KEY, Direction::Left, Direction::Right).await?;
+        if moved.is_none() {
+            break;
+        }
+        recovered += 1;
+    }
+    Ok(recovered + recover_orphaned(conn).await?)
+}
+
+// só move se o lease do INSTANCE_ID dono da lista (KEYS[2]) já expirou; no mesmo script para
+// não roubar itens de uma instância que reservou o id entre a checagem e o move
+static RECOVER_ORPHANED_SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(r#"
+if redis.call('EXISTS', KEYS[2]) == 1 then
+    return 0
+end
+local moved = 0
+while redis.call('LMOVE', KEYS[1], KEYS[3], 'LEFT', 'RIGHT') do
+    moved = moved + 1
+end
+return moved
+"#));
+
+async fn recover_orphaned(conn: &mut ConnectionManager) -> RedisResult<usize> {
+    let prefix = format!("{}:", QUEUE_PROCESSING_KEY);
+    let keys = scan_keys(conn, &format!("{}*", prefix)).await?;
+    let own = processing_key();
+    let mut recovered = 0;
+    for key in keys.iter().filter(|key| **key != own) {
+        let instance_id = &key[prefix.len()..];
+        let moved: usize = RECOVER_ORPHANED_SCRIPT
+            .key(key)
+            .key(format!("{}:{}", INSTANCE_KEY, instance_id))
+            .key(QUEUE_KEY)
+            .invoke_async(conn)
+            .await?;
+        if moved > 0 {
+            println!("[QUEUE] {} pagamentos da instância {} (sem lease) devolvidos para a fila", moved, instance_id);
+        }
+        recovered += moved;
+    }
+    Ok(recovered)
+}
+
+// tira da lista de processamento e agenda no ZSET de atrasados, com score = quando pode tentar de novo
+pub async fn schedule_retry(conn: &mut ConnectionManager, claimed: &[u8], payload: &[u8], due_ms: u64) -> RedisResult<()> {
+    pipe()
+        .atomic()
+        .zadd(QUEUE_DELAYED_KEY, payload, due_ms).ignore()
+        .lrem(processing_key(), 1, claimed).ignore()
+        .query_async(conn)
+        .await
+}
+
+static PROMOTE_SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(r#"
+local due = redis.call('ZRANGEBYSCORE', KEYS[1], '-inf', ARGV[1], 'LIMIT', 0, ARGV[2])
+for _, item in ipairs(due) do
+    redis.call('ZREM', KEYS[1], item)
+    redis.call('LPUSH', KEYS[2], item)
+end
+return #due
+"#));
+
+// move para a fila de trabalho os itens cujo horário já chegou
+pub async fn promote_due(conn: &mut ConnectionManager, now_ms: u64, limit: usize) -> RedisResult<usize> {
+    PROMOTE_SCRIPT
+        .key(QUEUE_DELAYED_KEY)
+        .key(QUEUE_KEY)
+        .arg(now_ms)
+        .arg(limit)
+        .invoke_async(conn)
+        .await
+}
+
+pub async fn dead_letter_payment(conn: &mut ConnectionManager, claimed: &[u8], entry: &DeadLetter) -> RedisResult<()> {
+    let json = serde_json::to_string(entry).unwrap();
+    pipe()
+        .atomic()
+        .hset(QUEUE_FAILED_KEY, &entry.payment.correlation_id, json).ignore()
+        .lrem(processing_key(), 1, claimed).ignore()
+        .query_async(conn)
+        .await
+}
+
+pub async fn list_dead_letters(conn: &mut ConnectionManager) -> RedisResult<Vec<DeadLetter>> {
+    let values: Vec<(String, String)> = conn.hgetall(QUEUE_FAILED_KEY).await?;
+    Ok(values.iter().filter_map(|(id, v)| parse_dead_letter(id, v)).collect())
+}
+
+pub async fn get_dead_letter(conn: &mut ConnectionManager, correlation_id: &str) -> RedisResult<Option<DeadLetter>> {
+    let value: Option<String> = conn.hget(QUEUE_FAILED_KEY, correlation_id).await?;
+    Ok(value.and_then(|v| parse_dead_letter(correlation_id, &v)))
+}
+
+// entrada ilegível não aparece na listagem, mas continua no hash (o discard-all apaga):
+// loga para não sumir sem explicação
+fn parse_dead_letter(correlation_id: &str, value: &str) -> Option<DeadLetter> {
+    match serde_json::from_str(value) {
+        Ok(entry) => Some(entry),
+        Err(e) => {
+            eprintln!("Dead letter {} ilegível, ignorada na listagem: {}", correlation_id, e);
+            None
+        }
+    }
+}
+
+// só quem conseguir remover a entrada recoloca na fila, evita replay duplicado
+static REPLAY_SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(r#"
+if redis.call('HDEL', KEYS[1], ARGV[1]) == 1 then
+    redis.call('LPUSH', KEYS[2], ARGV[2])
+    return 1
+end
+return 0
+"#));
+
+pub async fn replay_dead_letter(conn: &mut ConnectionManager, entry: DeadLetter) -> RedisResult<bool> {
+    let correlation_id = entry.payment.correlation_id.clone();
+    let payload = serde_json::to_string(&QueuedPayment::replay(entry, now_unix_ms())).unwrap();
+    let replayed: i32 = REPLAY_SCRIPT
+        .key(QUEUE_FAILED_KEY)
+        .key(QUEUE_KEY)
+        .arg(correlation_id)
+        .arg(payload)
+        .invoke_async(conn)
+        .await?;
+    Ok(replayed == 1)
+}
+
+pub async fn discard_dead_letter(conn: &mut ConnectionManager, correlation_id: &str) -> RedisResult<bool> {
+    let removed: i32 = conn.hdel(QUEUE_FAILED_KEY, correlation_id).await?;
+    Ok(removed == 1)
+}
+
+pub async fn discard_all_dead_letters(conn: &mut ConnectionManager) -> RedisResult<usize> {
+    // o DEL é ignorado, o EXEC volta só com o HLEN
+    let (count,): (usize,) = pipe()
+        .atomic()
+        .hlen(QUEUE_FAILED_KEY)
+        .del(QUEUE_FAILED_KEY).ignore()
+        .query_async(conn)
+        .await?;
+    Ok(count)
+}
+
+pub fn start_delayed_promoter(queue: Arc<dyn PaymentQueue>) {
+    tokio::spawn(async move {
+        loop {
+            match queue.promote_due(now_unix_ms(), 500).await {
+                // ainda tem item vencido, continua sem esperar
+                Ok(500) => continue,
+                Ok(_) => {}
+                Err(e) => eprintln!("Erro ao promover pagamentos atrasados: {}", e),
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+    });
+}
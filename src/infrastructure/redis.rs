@@ -1,6 +1,13 @@
+use once_cell::sync::Lazy;
 use redis::aio::ConnectionManager;
-use redis::{pipe, RedisError};
-use crate::infrastructure::config::{REDIS_URL};
+use redis::{AsyncCommands, RedisError, Script};
+use crate::domain::entities::PaymentRecord;
+use crate::domain::money::Money;
+use crate::domain::store::SummarySeries;
+use crate::infrastructure::config::{
+    DISPATCH_KEY, RECORDED_KEY, REDIS_URL, SUMMARY_BUCKET_MS, SUMMARY_BUCKET_SINCE_KEY, SUMMARY_BUCKET_WIDTH_KEY,
+};
+use crate::infrastructure::utils::now_unix_ms;
 
 pub async fn get_redis_connection() -> Result<ConnectionManager, RedisError> {
     let client = redis::Client::open(REDIS_URL.as_str().to_string())?;   //redis::Client::open(env::var("REDIS_URL").unwrap_or("redis://127.0.0.1:6379/".to_string()))?;
@@ -9,12 +16,541 @@ pub async fn get_redis_connection() -> Result<ConnectionManager, RedisError> {
 }
 
 
-pub async fn store_summary(conn: &mut ConnectionManager, key_prefix: &str, id: &str, amount: f64, timestamp_ms: f64) -> redis::RedisResult<()> {
+fn summary_key(processor: &str, suffix: &str) -> String {
+    format!("summary:{}:{}", processor, suffix)
+}
+
+// os contadores levam a largura no nome: com outro SUMMARY_BUCKET_MS os buckets antigos não se misturam.
+// Os scripts montam o mesmo nome em lua (BUCKET_KEY_LUA), com a largura que está gravada no redis
+fn bucket_key(processor: &str, suffix: &str, width: u64) -> String {
+    format!("summary:{}:{}:{}", processor, suffix, width)
+}
+
+const BUCKET_KEY_LUA: &str = r#"
+local function bucket_key(processor, suffix, width)
+    return 'summary:' .. processor .. ':' .. suffix .. ':' .. width
+end
+"#;
+
+// a largura dos buckets é uma só no cluster, a do SUMMARY_BUCKET_WIDTH_KEY: quem grava e quem lê
+// usam ela, não o SUMMARY_BUCKET_MS de cada instância. Só a subida troca a largura
+// (adopt_bucket_width), então instâncias com configurações diferentes num rolling deploy não
+// ficam alternando a chave. Ao trocar, o SUMMARY_BUCKET_SINCE_KEY guarda desde quando os buckets
+// da nova largura estão completos
+static ADOPT_BUCKET_WIDTH_SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(r#"
+if redis.call('GET', KEYS[1]) == ARGV[1] then
+    return 0
+end
+redis.call('SET', KEYS[1], ARGV[1])
+redis.call('HSET', KEYS[2], ARGV[1], ARGV[2])
+return 1
+"#));
+
+// true se a largura gravada mudou para o SUMMARY_BUCKET_MS desta instância
+pub async fn adopt_bucket_width(conn: &mut ConnectionManager) -> redis::RedisResult<bool> {
+    let changed: i32 = ADOPT_BUCKET_WIDTH_SCRIPT
+        .key(SUMMARY_BUCKET_WIDTH_KEY)
+        .key(SUMMARY_BUCKET_SINCE_KEY)
+        .arg(*SUMMARY_BUCKET_MS)
+        .arg(now_unix_ms())
+        .invoke_async(conn)
+        .await?;
+    Ok(changed == 1)
+}
+
+// HSETNX no RECORDED_KEY garante que um correlationId só soma uma vez, em qualquer processador;
+// além do item, soma quantidade e valor no bucket de tempo dele, na largura gravada em KEYS[5]
+// (se ainda não tem, como depois de um purge, assume ARGV[5] e marca desde quando em KEYS[6]).
+// Contabilizado, o pagamento não precisa mais ficar fixado no processador (KEYS[4])
+static STORE_SUMMARY_SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(&format!("{}{}", BUCKET_KEY_LUA, r#"
+redis.call('HDEL', KEYS[4], ARGV[1])
+if redis.call('HSETNX', KEYS[1], ARGV[1], ARGV[4]) == 1 then
+    redis.call('HSET', KEYS[2], ARGV[1], ARGV[2])
+    redis.call('ZADD', KEYS[3], ARGV[3], ARGV[1])
+    local width = redis.call('GET', KEYS[5])
+    if not width then
+        width = ARGV[5]
+        redis.call('SET', KEYS[5], width)
+        redis.call('HSET', KEYS[6], width, ARGV[6])
+    end
+    local bucket = math.floor(tonumber(ARGV[3]) / tonumber(width)) * tonumber(width)
+    local field = string.format('%d', bucket)
+    redis.call('ZADD', bucket_key(ARGV[4], 'buckets', width), bucket, field)
+    redis.call('HINCRBY', bucket_key(ARGV[4], 'counts', width), field, 1)
+    redis.call('HINCRBY', bucket_key(ARGV[4], 'sums', width), field, ARGV[2])
+    return 1
+end
+return 0
+"#)));
+
+pub async fn store_summary(conn: &mut ConnectionManager, key_prefix: &str, id: &str, amount: Money, timestamp_ms: i64) -> redis::RedisResult<bool> {
     //println!("store_summary => key_prefix: {}, id: {}, amount: {}, timestamp: {}", key_prefix, id, amount, timestamp_ms);
-    pipe()
-        .atomic()
-        .hset(format!("summary:{}:data", key_prefix), id, amount)
-        .zadd(format!("summary:{}:history", key_prefix), id, timestamp_ms)
+    let stored: i32 = STORE_SUMMARY_SCRIPT
+        .key(RECORDED_KEY)
+        .key(summary_key(key_prefix, "data"))
+        .key(summary_key(key_prefix, "history"))
+        .key(DISPATCH_KEY)
+        .key(SUMMARY_BUCKET_WIDTH_KEY)
+        .key(SUMMARY_BUCKET_SINCE_KEY)
+        .arg(id)
+        .arg(amount)
+        .arg(timestamp_ms)
+        .arg(key_prefix)
+        .arg(*SUMMARY_BUCKET_MS)
+        .arg(now_unix_ms())
+        .invoke_async(conn)
+        .await?;
+    Ok(stored == 1)
+}
+
+// summary_ranges em lua, lendo a largura (KEYS[1]) e desde quando ela está completa (KEYS[2])
+// na mesma chamada. Devolve as faixas de itens (ponta inicial e final), a de buckets inteiros e
+// a largura, ou só os itens se não há bucket utilizável (`usable` recebe a largura).
+// Os limites saem formatados com %.0f: o tostring do lua corta em 14 dígitos
+const SUMMARY_RANGES_LUA: &str = r#"
+local function ms(x)
+    return string.format('%.0f', x)
+end
+local function summary_ranges(from, to, usable)
+    local width = redis.call('GET', KEYS[1])
+    local since = width and usable(tonumber(width)) and tonumber(redis.call('HGET', KEYS[2], width) or '')
+    if not since then
+        return {{from, to}}, nil, nil
+    end
+    local w = tonumber(width)
+    local start = math.max(from, since)
+    local first = start - start % w
+    if first < start then
+        first = first + w
+    end
+    local last = math.floor((to + 1) / w) * w - w
+    if first > last then
+        return {{from, to}}, nil, nil
+    end
+    return {{from, first - 1}, {last + w, to}}, {first, last}, width
+end
+"#;
+
+// history e data de cada processador: a ordem que os scripts de summary esperam depois das
+// chaves de largura
+fn summary_invocation<'a>(script: &'a Script, processors: &[&str]) -> redis::ScriptInvocation<'a> {
+    let mut invocation = script.prepare_invoke();
+    invocation.key(SUMMARY_BUCKET_WIDTH_KEY).key(SUMMARY_BUCKET_SINCE_KEY);
+    for processor in processors {
+        invocation.key(summary_key(processor, "history")).key(summary_key(processor, "data"));
+    }
+    invocation
+}
+
+// uma chamada só para todos os processadores: visão consistente entre eles e só os totais voltam.
+// KEYS: largura, desde quando, e history/data de cada processador; ARGV: from, to e os nomes dos
+// processadores (para os contadores da largura gravada)
+static SUMMARY_TOTALS_SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(&format!("{}{}{}", BUCKET_KEY_LUA, SUMMARY_RANGES_LUA, r#"
+local items, buckets, width = summary_ranges(tonumber(ARGV[1]), tonumber(ARGV[2]), function() return true end)
+local result = {}
+for p = 3, #ARGV do
+    local history, data = KEYS[2 * p - 3], KEYS[2 * p - 2]
+    local count, total = 0, 0
+    for _, range in ipairs(items) do
+        for _, id in ipairs(redis.call('ZRANGEBYSCORE', history, ms(range[1]), ms(range[2]))) do
+            count = count + 1
+            total = total + (tonumber(redis.call('HGET', data, id)) or 0)
+        end
+    end
+    if buckets then
+        local counts, sums = bucket_key(ARGV[p], 'counts', width), bucket_key(ARGV[p], 'sums', width)
+        for _, bucket in ipairs(redis.call('ZRANGEBYSCORE', bucket_key(ARGV[p], 'buckets', width), ms(buckets[1]), ms(buckets[2]))) do
+            count = count + (tonumber(redis.call('HGET', counts, bucket)) or 0)
+            total = total + (tonumber(redis.call('HGET', sums, bucket)) or 0)
+        end
+    end
+    table.insert(result, count)
+    table.insert(result, total)
+end
+return result
+"#)));
+
+// quantidade e soma em [from, to] (ms, inclusivo) de cada processador: buckets inteiros dentro do
+// intervalo saem dos contadores, as pontas que cortam um bucket no meio são somadas item a item
+pub async fn summary_totals(conn: &mut ConnectionManager, processors: &[&str], from: i64, to: i64) -> redis::RedisResult<Vec<(u64, Money)>> {
+    if from > to || processors.is_empty() {
+        return Ok(vec![(0, Money::ZERO); processors.len()]);
+    }
+    let mut invocation = summary_invocation(&SUMMARY_TOTALS_SCRIPT, processors);
+    invocation.arg(from).arg(to).arg(processors);
+    let totals: Vec<i64> = invocation.invoke_async(conn).await?;
+    Ok(totals.chunks(2)
+        .map(|pair| (pair[0].max(0) as u64, Money::from_cents(pair.get(1).copied().unwrap_or(0))))
+        .collect())
+}
+
+// mesmas faixas do SUMMARY_TOTALS_SCRIPT, mas agrupando por intervalo de ARGV[3] ms (alinhado ao epoch);
+// os contadores só servem quando cada bucket cabe inteiro num intervalo, senão conta item a item.
+// Devolve quádruplas [índice do processador, início do intervalo, quantidade, soma] só dos intervalos com dados
+static SUMMARY_SERIES_SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(&format!("{}{}{}", BUCKET_KEY_LUA, SUMMARY_RANGES_LUA, r#"
+local interval = tonumber(ARGV[3])
+local items, buckets, width = summary_ranges(tonumber(ARGV[1]), tonumber(ARGV[2]), function(w) return interval % w == 0 end)
+local result = {}
+for p = 4, #ARGV do
+    local history, data = KEYS[2 * p - 5], KEYS[2 * p - 4]
+    local counts, totals = {}, {}
+    local function add(ts, count, amount)
+        local slot = math.floor(ts / interval) * interval
+        counts[slot] = (counts[slot] or 0) + count
+        totals[slot] = (totals[slot] or 0) + amount
+    end
+    for _, range in ipairs(items) do
+        local found = redis.call('ZRANGEBYSCORE', history, ms(range[1]), ms(range[2]), 'WITHSCORES')
+        for j = 1, #found, 2 do
+            add(tonumber(found[j + 1]), 1, tonumber(redis.call('HGET', data, found[j])) or 0)
+        end
+    end
+    if buckets then
+        local bucket_counts, bucket_sums = bucket_key(ARGV[p], 'counts', width), bucket_key(ARGV[p], 'sums', width)
+        local found = redis.call('ZRANGEBYSCORE', bucket_key(ARGV[p], 'buckets', width), ms(buckets[1]), ms(buckets[2]), 'WITHSCORES')
+        for j = 1, #found, 2 do
+            add(tonumber(found[j + 1]),
+                tonumber(redis.call('HGET', bucket_counts, found[j])) or 0,
+                tonumber(redis.call('HGET', bucket_sums, found[j])) or 0)
+        end
+    end
+    for slot, count in pairs(counts) do
+        table.insert(result, p - 4)
+        table.insert(result, slot)
+        table.insert(result, count)
+        table.insert(result, totals[slot])
+    end
+end
+return result
+"#)));
+
+// quantidade e soma por intervalo de `interval` ms dentro de [from, to], na ordem dos processadores
+pub async fn summary_series(
+    conn: &mut ConnectionManager,
+    processors: &[&str],
+    from: i64,
+    to: i64,
+    interval: i64,
+) -> redis::RedisResult<SummarySeries> {
+    let mut series = SummarySeries::new();
+    if from > to || processors.is_empty() || interval <= 0 {
+        return Ok(series);
+    }
+    let mut invocation = summary_invocation(&SUMMARY_SERIES_SCRIPT, processors);
+    invocation.arg(from).arg(to).arg(interval).arg(processors);
+    let points: Vec<i64> = invocation.invoke_async(conn).await?;
+    for point in points.chunks_exact(4) {
+        let (index, slot, count, total) = (point[0] as usize, point[1], point[2], point[3]);
+        let totals = series.entry(slot).or_insert_with(|| vec![(0, Money::ZERO); processors.len()]);
+        if let Some(entry) = totals.get_mut(index) {
+            *entry = (count.max(0) as u64, Money::from_cents(total));
+        }
+    }
+    Ok(series)
+}
+
+// o RECORDED_KEY diz em qual processador o pagamento foi contabilizado, valor e horário ficam nas chaves dele
+pub async fn lookup_payment(conn: &mut ConnectionManager, id: &str) -> redis::RedisResult<Option<PaymentRecord>> {
+    let Some(processor): Option<String> = conn.hget(RECORDED_KEY, id).await? else {
+        return Ok(None);
+    };
+    let (amount, timestamp): (Option<Money>, Option<f64>) = redis::pipe()
+        .hget(summary_key(&processor, "data"), id)
+        .zscore(summary_key(&processor, "history"), id)
+        .query_async(conn)
+        .await?;
+    Ok(Some(PaymentRecord {
+        correlation_id: id.to_string(),
+        processor,
+        amount: amount.unwrap_or(Money::ZERO),
+        requested_at: timestamp.unwrap_or(0.0) as i64,
+    }))
+}
+
+// pagamentos de um processador em [from, to] (ms, inclusivo), em ordem de horário
+pub async fn payments_in_range(conn: &mut ConnectionManager, processor: &str, from: i64, to: i64) -> redis::RedisResult<Vec<PaymentRecord>> {
+    if from > to {
+        return Ok(Vec::new());
+    }
+    let items: Vec<(String, f64)> = conn.zrangebyscore_withscores(summary_key(processor, "history"), from, to).await?;
+    if items.is_empty() {
+        return Ok(Vec::new());
+    }
+    let ids: Vec<&str> = items.iter().map(|(id, _)| id.as_str()).collect();
+    let amounts: Vec<Option<Money>> = redis::cmd("HMGET")
+        .arg(summary_key(processor, "data"))
+        .arg(&ids)
         .query_async(conn)
+        .await?;
+    Ok(items.iter().zip(amounts)
+        .map(|((id, timestamp), amount)| PaymentRecord {
+            correlation_id: id.clone(),
+            processor: processor.to_string(),
+            amount: amount.unwrap_or(Money::ZERO),
+            requested_at: *timestamp as i64,
+        })
+        .collect())
+}
+
+// apaga o que foi contabilizado e os pagamentos fixados em processador, com os contadores de todas
+// as larguras já usadas; os correlationIds aceitos ficam com a fila (queue::purge_accepted)
+pub async fn purge_payments(conn: &mut ConnectionManager, processors: &[&str]) -> redis::RedisResult<()> {
+    let mut widths: Vec<u64> = conn.hkeys(SUMMARY_BUCKET_SINCE_KEY).await?;
+    widths.push(*SUMMARY_BUCKET_MS);
+    let mut keys = vec![
+        RECORDED_KEY.to_string(),
+        DISPATCH_KEY.to_string(),
+        SUMMARY_BUCKET_WIDTH_KEY.to_string(),
+        SUMMARY_BUCKET_SINCE_KEY.to_string(),
+    ];
+    for processor in processors {
+        keys.push(summary_key(processor, "history"));
+        keys.push(summary_key(processor, "data"));
+        for width in &widths {
+            for suffix in ["buckets", "counts", "sums"] {
+                keys.push(bucket_key(processor, suffix, *width));
+            }
+        }
+    }
+    conn.del(keys).await
+}
+
+pub async fn is_recorded(conn: &mut ConnectionManager, id: &str) -> redis::RedisResult<bool> {
+    conn.hexists(RECORDED_KEY, id).await
+}
+
+// fixa o processador do pagamento: devolve o já fixado ou fixa o informado
+static PIN_PROCESSOR_SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(r#"
+redis.call('HSETNX', KEYS[1], ARGV[1], ARGV[2])
+return redis.call('HGET', KEYS[1], ARGV[1])
+"#));
+
+pub async fn pin_processor(conn: &mut ConnectionManager, id: &str, processor: &str) -> redis::RedisResult<String> {
+    PIN_PROCESSOR_SCRIPT
+        .key(DISPATCH_KEY)
+        .arg(id)
+        .arg(processor)
+        .invoke_async(conn)
         .await
 }
+
+// só libera se o pagamento ainda estiver fixado nesse processador
+static RELEASE_PROCESSOR_SCRIPT: Lazy<Script> = Lazy::new(|| Script::new(r#"
+if redis.call('HGET', KEYS[1], ARGV[1]) == ARGV[2] then
+    return redis.call('HDEL', KEYS[1], ARGV[1])
+end
+return 0
+"#));
+
+pub async fn release_processor(conn: &mut ConnectionManager, id: &str, processor: &str) -> redis::RedisResult<()> {
+    RELEASE_PROCESSOR_SCRIPT
+        .key(DISPATCH_KEY)
+        .arg(id)
+        .arg(processor)
+        .invoke_async(conn)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    // a conta do SUMMARY_RANGES_LUA: itens antes do primeiro bucket inteiro, buckets inteiros e itens
+    // depois do último. Os buckets só valem a partir de `buckets_from` (desde quando a largura
+    // está completa); sem ele, tudo sai item a item
+    fn summary_ranges(from: i64, to: i64, width: i64, buckets_from: Option<i64>) -> [(i64, i64); 3] {
+        let Some(buckets_from) = buckets_from else {
+            return [(from, to), (1, 0), (1, 0)];
+        };
+        // primeiro bucket inteiro a partir de from (e de buckets_from), e último que termina em to ou antes
+        let start = from.max(buckets_from);
+        let first = start.div_euclid(width) * width + if start.rem_euclid(width) == 0 { 0 } else { width };
+        let last = (to + 1).div_euclid(width) * width - width;
+        if first > last {
+            [(from, to), (1, 0), (1, 0)]
+        } else {
+            [(from, first - 1), (first, last), (last + width, to)]
+        }
+    }
+
+    const EMPTY: (i64, i64) = (1, 0);
+    // buckets completos desde sempre
+    const ALL: Option<i64> = Some(i64::MIN);
+
+    // as três faixas, com os buckets abertos em ms, cobrem exatamente [from, to] sem sobreposição
+    fn covered(ranges: [(i64, i64); 3], width: i64) -> Vec<i64> {
+        let [head, buckets, tail] = ranges;
+        let mut ms: Vec<i64> = (head.0..=head.1).collect();
+        if buckets.0 <= buckets.1 {
+            assert_eq!(buckets.0.rem_euclid(width), 0);
+            assert_eq!(buckets.1.rem_euclid(width), 0);
+            ms.extend(buckets.0..buckets.1 + width);
+        }
+        ms.extend(tail.0..=tail.1);
+        ms
+    }
+
+    #[test]
+    fn aligned_range_is_all_buckets() {
+        assert_eq!(summary_ranges(1000, 2999, 1000, ALL), [(1000, 999), (1000, 2000), (3000, 2999)]);
+    }
+
+    #[test]
+    fn unaligned_range_has_item_edges() {
+        assert_eq!(summary_ranges(1500, 4200, 1000, ALL), [(1500, 1999), (2000, 3000), (4000, 4200)]);
+    }
+
+    #[test]
+    fn exactly_one_bucket() {
+        assert_eq!(summary_ranges(1000, 1999, 1000, ALL), [(1000, 999), (1000, 1000), (2000, 1999)]);
+    }
+
+    #[test]
+    fn narrower_than_a_bucket_is_items_only() {
+        assert_eq!(summary_ranges(1200, 1800, 1000, ALL), [(1200, 1800), EMPTY, EMPTY]);
+        assert_eq!(summary_ranges(1000, 1998, 1000, ALL), [(1000, 1998), EMPTY, EMPTY]);
+        assert_eq!(summary_ranges(1001, 1999, 1000, ALL), [(1001, 1999), EMPTY, EMPTY]);
+    }
+
+    #[test]
+    fn zero_and_negative_bounds() {
+        assert_eq!(summary_ranges(0, 0, 1000, ALL), [(0, 0), EMPTY, EMPTY]);
+        assert_eq!(summary_ranges(0, 999, 1000, ALL), [(0, -1), (0, 0), (1000, 999)]);
+        assert_eq!(summary_ranges(-1500, -1, 1000, ALL), [(-1500, -1001), (-1000, -1000), (0, -1)]);
+        assert_eq!(summary_ranges(-2000, 500, 1000, ALL), [(-2000, -2001), (-2000, -1000), (0, 500)]);
+    }
+
+    #[test]
+    fn without_buckets_everything_is_items() {
+        assert_eq!(summary_ranges(1000, 5999, 1000, None), [(1000, 5999), EMPTY, EMPTY]);
+    }
+
+    #[test]
+    fn ranges_cover_the_interval_exactly() {
+        for width in [1, 7, 1000] {
+            for from in -2050..-1950 {
+                for to in [from, from + 1, from + width - 1, from + width, from + 3 * width + 5] {
+                    let expected: Vec<i64> = (from..=to).collect();
+                    assert_eq!(covered(summary_ranges(from, to, width, ALL), width), expected, "{from}..={to} / {width}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn buckets_only_after_they_are_complete() {
+        // buckets de 1000 completos desde 2500: o bucket 2000 ainda sai item a item
+        assert_eq!(summary_ranges(1000, 5999, 1000, Some(2500)), [(1000, 2999), (3000, 5000), (6000, 5999)]);
+        assert_eq!(summary_ranges(1000, 5999, 1000, Some(3000)), [(1000, 2999), (3000, 5000), (6000, 5999)]);
+        assert_eq!(summary_ranges(1000, 5999, 1000, Some(500)), [(1000, 999), (1000, 5000), (6000, 5999)]);
+        assert_eq!(summary_ranges(1000, 5999, 1000, Some(5500)), [(1000, 5999), EMPTY, EMPTY]);
+    }
+
+    // o que o ADOPT_BUCKET_WIDTH_SCRIPT, o STORE_SUMMARY_SCRIPT e o SUMMARY_TOTALS_SCRIPT fazem, sem redis
+    #[derive(Default)]
+    struct Summary {
+        history: Vec<(i64, i64)>,
+        buckets: BTreeMap<(i64, i64), (u64, i64)>,
+        width: Option<i64>,
+        since: BTreeMap<i64, i64>,
+    }
+
+    impl Summary {
+        fn adopt(&mut self, width: i64, now: i64) {
+            if self.width != Some(width) {
+                self.width = Some(width);
+                self.since.insert(width, now);
+            }
+        }
+
+        // `configured` é o SUMMARY_BUCKET_MS de quem grava: só vale se ainda não tem largura gravada
+        fn record(&mut self, timestamp: i64, amount: i64, configured: i64, now: i64) {
+            self.history.push((timestamp, amount));
+            if self.width.is_none() {
+                self.adopt(configured, now);
+            }
+            let width = self.width.unwrap();
+            let bucket = self.buckets.entry((width, timestamp.div_euclid(width) * width)).or_default();
+            bucket.0 += 1;
+            bucket.1 += amount;
+        }
+
+        fn totals(&self, from: i64, to: i64) -> (u64, i64) {
+            let Some(width) = self.width else {
+                return self.expected(from, to);
+            };
+            let [head, buckets, tail] = summary_ranges(from, to, width, self.since.get(&width).copied());
+            let mut totals = (0, 0);
+            for (timestamp, amount) in &self.history {
+                if (head.0..=head.1).contains(timestamp) || (tail.0..=tail.1).contains(timestamp) {
+                    totals = (totals.0 + 1, totals.1 + amount);
+                }
+            }
+            for ((bucket_width, start), (count, sum)) in &self.buckets {
+                if *bucket_width == width && (buckets.0..=buckets.1).contains(start) {
+                    totals = (totals.0 + count, totals.1 + sum);
+                }
+            }
+            totals
+        }
+
+        fn expected(&self, from: i64, to: i64) -> (u64, i64) {
+            self.history.iter()
+                .filter(|(timestamp, _)| (from..=to).contains(timestamp))
+                .fold((0, 0), |(count, sum), (_, amount)| (count + 1, sum + amount))
+        }
+    }
+
+    fn assert_totals(summary: &Summary, last: i64) {
+        for (from, to) in [(0, last), (-5000, last + 5000), (1, last / 2), (last / 3, last - 1), (last - 800, last)] {
+            assert_eq!(summary.totals(from, to), summary.expected(from, to), "{from}..={to}");
+        }
+    }
+
+    #[test]
+    fn changing_the_width_over_existing_data_does_not_lose_payments() {
+        let mut summary = Summary::default();
+        let mut now = 0;
+        // cada fase sobe com uma largura; o requestedAt pode ser até 700ms anterior à gravação
+        for (width, payments) in [(1000, 300), (60_000, 300), (1000, 300), (7, 50)] {
+            summary.adopt(width, now);
+            for i in 0..payments {
+                now += 37;
+                summary.record(now - (i % 20) * 35, 100 + i, width, now);
+            }
+            assert_totals(&summary, now);
+        }
+    }
+
+    #[test]
+    fn instances_with_different_widths_write_with_the_stored_one() {
+        let mut summary = Summary::default();
+        let mut now = 0;
+        summary.adopt(1000, now);
+        // rolling deploy: a instância nova já subiu com 5000, a antiga continua gravando
+        for i in 0..600 {
+            now += 37;
+            if i == 300 {
+                summary.adopt(5000, now);
+            }
+            let configured = if i % 2 == 0 { 1000 } else { 5000 };
+            summary.record(now - (i % 20) * 35, 100 + i, configured, now);
+            if i % 50 == 0 {
+                assert_totals(&summary, now);
+            }
+        }
+        assert_eq!(summary.width, Some(5000));
+        assert_totals(&summary, now);
+    }
+
+    #[test]
+    fn without_a_stored_width_the_first_write_sets_it() {
+        let mut summary = Summary::default();
+        summary.record(1500, 10, 1000, 2000);
+        assert_eq!((summary.width, summary.since.get(&1000)), (Some(1000), Some(&2000)));
+        // já tem largura gravada: a configuração de quem grava não muda nada
+        summary.record(2500, 10, 5000, 3000);
+        assert_eq!(summary.width, Some(1000));
+        assert_totals(&summary, 3000);
+    }
+}
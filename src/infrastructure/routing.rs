@@ -0,0 +1,323 @@
+use crate::domain::entities::{HealthStatusAll, ProcessorConfig, ProcessorDecision, ProcessorStats, RoutingStats};
+use crate::infrastructure::config::{PROCESSORS, ROUTING_LATENCY_COST_PER_MS, ROUTING_STRATEGY_NAME};
+use crate::infrastructure::utils::elapsed_since;
+use crate::HealthResponse;
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+pub trait RoutingStrategy: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn decide(&self, health: &HealthStatusAll, stats: &RoutingStats) -> ProcessorDecision;
+}
+
+pub static ROUTING_STRATEGY: Lazy<Box<dyn RoutingStrategy>> = Lazy::new(|| {
+    let strategy = strategy_from_name(ROUTING_STRATEGY_NAME.as_str(), &PROCESSORS);
+    println!("[ROUTING] Estratégia: {}", strategy.name());
+    strategy
+});
+
+pub fn strategy_from_name(name: &str, processors: &[ProcessorConfig]) -> Box<dyn RoutingStrategy> {
+    let processors = processors.to_vec();
+    match name {
+        "fee-aware" => Box::new(FeeAware {
+            processors,
+            latency_cost_per_ms: *ROUTING_LATENCY_COST_PER_MS,
+        }),
+        "latency-aware" => Box::new(LatencyAware { processors }),
+        "weighted-split" => Box::new(WeightedSplit::new(processors)),
+        "default-first" | "priority-first" => Box::new(DefaultFirst { processors, failover_after: Duration::from_secs(3) }),
+        other => {
+            eprintln!("[ROUTING] Estratégia desconhecida {}, usando default-first", other);
+            Box::new(DefaultFirst { processors, failover_after: Duration::from_secs(3) })
+        }
+    }
+}
+
+// processadores que não estão falhando, na ordem de prioridade
+fn available<'a>(processors: &'a [ProcessorConfig], health: &HealthStatusAll) -> Vec<&'a ProcessorConfig> {
+    processors.iter().filter(|p| !health.is_failing(&p.name)).collect()
+}
+
+fn route(processor: &ProcessorConfig) -> ProcessorDecision {
+    ProcessorDecision::PROCESSOR(processor.name.clone())
+}
+
+// latência medida nas chamadas reais quando houver, senão o que o processador informa no health
+fn observed_latency(health: Option<&HealthResponse>, stats: &ProcessorStats) -> f64 {
+    if stats.calls > 0 {
+        stats.ewma_ms
+    } else {
+        health.map(|h| h.min_response_time.max(0) as f64).unwrap_or(f64::MAX)
+    }
+}
+
+// escolhe o disponível com a maior nota; empate fica com o de maior prioridade
+fn best_by<F: Fn(&ProcessorConfig) -> f64>(candidates: &[&ProcessorConfig], score: F) -> ProcessorDecision {
+    let mut best: Option<(&ProcessorConfig, f64)> = None;
+    for processor in candidates {
+        let value = score(processor);
+        if best.map(|(_, best_value)| value > best_value).unwrap_or(true) {
+            best = Some((processor, value));
+        }
+    }
+    best.map(|(processor, _)| route(processor)).unwrap_or(ProcessorDecision::FAILING)
+}
+
+// prefere o de maior prioridade e só passa para o próximo depois que ele está falhando há algum tempo
+pub struct DefaultFirst {
+    pub processors: Vec<ProcessorConfig>,
+    pub failover_after: Duration,
+}
+
+impl RoutingStrategy for DefaultFirst {
+    fn name(&self) -> &'static str {
+        "default-first"
+    }
+
+    fn decide(&self, health: &HealthStatusAll, _stats: &RoutingStats) -> ProcessorDecision {
+        if available(&self.processors, health).is_empty() {
+            return ProcessorDecision::FAILING;
+        }
+
+        for processor in &self.processors {
+            let Some(processor_health) = health.get(&processor.name) else {
+                continue;
+            };
+            if !processor_health.failing {
+                return route(processor);
+            }
+            let failing_for = processor_health.failing_since.and_then(elapsed_since);
+            if failing_for.map(|elapsed| elapsed <= self.failover_after).unwrap_or(true) {
+                return route(processor);
+            }
+        }
+
+        ProcessorDecision::FAILING
+    }
+}
+
+// maximiza a receita líquida esperada: o que sobra da taxa vezes a chance de sucesso,
+// menos um custo proporcional à latência
+pub struct FeeAware {
+    pub processors: Vec<ProcessorConfig>,
+    pub latency_cost_per_ms: f64,
+}
+
+impl RoutingStrategy for FeeAware {
+    fn name(&self) -> &'static str {
+        "fee-aware"
+    }
+
+    fn decide(&self, health: &HealthStatusAll, stats: &RoutingStats) -> ProcessorDecision {
+        let candidates = available(&self.processors, health);
+        best_by(&candidates, |processor| {
+            let processor_stats = stats.get(&processor.name);
+            let latency = observed_latency(health.get(&processor.name), &processor_stats);
+            (1.0 - processor.fee) * (1.0 - processor_stats.error_rate()) - self.latency_cost_per_ms * latency
+        })
+    }
+}
+
+// vai para o processador que responde mais rápido
+pub struct LatencyAware {
+    pub processors: Vec<ProcessorConfig>,
+}
+
+impl RoutingStrategy for LatencyAware {
+    fn name(&self) -> &'static str {
+        "latency-aware"
+    }
+
+    fn decide(&self, health: &HealthStatusAll, stats: &RoutingStats) -> ProcessorDecision {
+        let candidates = available(&self.processors, health);
+        best_by(&candidates, |processor| {
+            -observed_latency(health.get(&processor.name), &stats.get(&processor.name))
+        })
+    }
+}
+
+// divide os pagamentos entre os processadores disponíveis na proporção dos pesos
+pub struct WeightedSplit {
+    pub processors: Vec<ProcessorConfig>,
+    counter: AtomicU64,
+}
+
+impl WeightedSplit {
+    pub fn new(processors: Vec<ProcessorConfig>) -> Self {
+        WeightedSplit { processors, counter: AtomicU64::new(0) }
+    }
+}
+
+impl RoutingStrategy for WeightedSplit {
+    fn name(&self) -> &'static str {
+        "weighted-split"
+    }
+
+    fn decide(&self, health: &HealthStatusAll, _stats: &RoutingStats) -> ProcessorDecision {
+        let candidates = available(&self.processors, health);
+        let total: u64 = candidates.iter().map(|p| p.weight as u64).sum();
+        if total == 0 {
+            return candidates.first().map(|p| route(p)).unwrap_or(ProcessorDecision::FAILING);
+        }
+        let mut slot = self.counter.fetch_add(1, Ordering::Relaxed) % total;
+        for processor in candidates {
+            if slot < processor.weight as u64 {
+                return route(processor);
+            }
+            slot -= processor.weight as u64;
+        }
+        ProcessorDecision::FAILING
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{strategy_from_name, DefaultFirst, FeeAware, LatencyAware, RoutingStrategy, WeightedSplit};
+    use crate::domain::entities::{HealthStatusAll, ProcessorConfig, ProcessorDecision, ProcessorStats, RoutingStats};
+    use crate::infrastructure::utils::now_unix_ms;
+    use crate::HealthResponse;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    fn processor(name: &str, fee: f64, weight: u32) -> ProcessorConfig {
+        ProcessorConfig { name: name.to_string(), url: String::new(), priority: 0, fee, weight }
+    }
+
+    fn processors() -> Vec<ProcessorConfig> {
+        vec![processor("default", 0.05, 80), processor("fallback", 0.15, 20)]
+    }
+
+    // (nome, failing há quantos ms (None = ok), minResponseTime)
+    fn health(entries: &[(&str, Option<u64>, i64)]) -> HealthStatusAll {
+        let processors = entries.iter()
+            .map(|(name, failing_for, min_response_time)| (name.to_string(), HealthResponse {
+                failing: failing_for.is_some(),
+                min_response_time: *min_response_time,
+                failing_since: failing_for.map(|ms| now_unix_ms() - ms),
+            }))
+            .collect();
+        HealthStatusAll { processors, updated_at: now_unix_ms() }
+    }
+
+    // (nome, chamadas, erros, ewma em ms)
+    fn stats(entries: &[(&str, u64, u64, f64)]) -> RoutingStats {
+        let processors: BTreeMap<String, ProcessorStats> = entries.iter()
+            .map(|(name, calls, errors, ewma_ms)| (name.to_string(), ProcessorStats {
+                calls: *calls,
+                errors: *errors,
+                ewma_ms: *ewma_ms,
+                ..Default::default()
+            }))
+            .collect();
+        RoutingStats { processors }
+    }
+
+    fn to(name: &str) -> ProcessorDecision {
+        ProcessorDecision::PROCESSOR(name.to_string())
+    }
+
+    #[test]
+    fn default_first_waits_the_failover_window() {
+        let strategy = DefaultFirst { processors: processors(), failover_after: Duration::from_secs(3) };
+        let cases = [
+            (health(&[("default", None, 0), ("fallback", None, 0)]), to("default")),
+            // falhando há pouco tempo: ainda insiste no default
+            (health(&[("default", Some(1_000), 0), ("fallback", None, 0)]), to("default")),
+            (health(&[("default", Some(5_000), 0), ("fallback", None, 0)]), to("fallback")),
+            (health(&[("default", Some(5_000), 0), ("fallback", Some(5_000), 0)]), ProcessorDecision::FAILING),
+            (health(&[("default", Some(1_000), 0), ("fallback", Some(1_000), 0)]), ProcessorDecision::FAILING),
+            // sem health do default, vai direto para o próximo
+            (health(&[("fallback", None, 0)]), to("fallback")),
+        ];
+        for (index, (health, expected)) in cases.iter().enumerate() {
+            assert_eq!(&strategy.decide(health, &RoutingStats::default()), expected, "caso {}", index);
+        }
+    }
+
+    #[test]
+    fn fee_aware_trades_fee_for_latency_and_errors() {
+        let strategy = FeeAware { processors: processors(), latency_cost_per_ms: 0.001 };
+        let healthy = health(&[("default", None, 0), ("fallback", None, 0)]);
+        let cases = [
+            // mesma latência: a taxa menor ganha
+            (stats(&[("default", 10, 0, 10.0), ("fallback", 10, 0, 10.0)]), to("default")),
+            // default 0.95 - 0.2 < fallback 0.85 - 0.01
+            (stats(&[("default", 10, 0, 200.0), ("fallback", 10, 0, 10.0)]), to("fallback")),
+            // default 0.95 - 0.05 > fallback 0.85 - 0.01
+            (stats(&[("default", 10, 0, 50.0), ("fallback", 10, 0, 10.0)]), to("default")),
+            // metade das chamadas do default falhando: 0.475 - 0.01 < 0.84
+            (stats(&[("default", 10, 5, 10.0), ("fallback", 10, 0, 10.0)]), to("fallback")),
+        ];
+        for (index, (stats, expected)) in cases.iter().enumerate() {
+            assert_eq!(&strategy.decide(&healthy, stats), expected, "caso {}", index);
+        }
+
+        // sem chamadas ainda, usa o minResponseTime do health
+        let slow_default = health(&[("default", None, 300), ("fallback", None, 5)]);
+        assert_eq!(strategy.decide(&slow_default, &RoutingStats::default()), to("fallback"));
+
+        let failing_default = health(&[("default", Some(1), 0), ("fallback", None, 0)]);
+        assert_eq!(strategy.decide(&failing_default, &RoutingStats::default()), to("fallback"));
+        let all_failing = health(&[("default", Some(1), 0), ("fallback", Some(1), 0)]);
+        assert_eq!(strategy.decide(&all_failing, &RoutingStats::default()), ProcessorDecision::FAILING);
+    }
+
+    #[test]
+    fn latency_aware_picks_the_fastest() {
+        let strategy = LatencyAware { processors: processors() };
+        let cases = [
+            (health(&[("default", None, 100), ("fallback", None, 20)]), stats(&[]), to("fallback")),
+            (health(&[("default", None, 20), ("fallback", None, 100)]), stats(&[]), to("default")),
+            // chamadas reais valem mais que o health
+            (health(&[("default", None, 100), ("fallback", None, 20)]), stats(&[("default", 5, 0, 10.0), ("fallback", 5, 0, 30.0)]), to("default")),
+            // empate fica com o de maior prioridade
+            (health(&[("default", None, 20), ("fallback", None, 20)]), stats(&[]), to("default")),
+            (health(&[("default", None, 20), ("fallback", Some(1), 5)]), stats(&[]), to("default")),
+            (health(&[("default", Some(1), 20), ("fallback", Some(1), 5)]), stats(&[]), ProcessorDecision::FAILING),
+        ];
+        for (index, (health, stats, expected)) in cases.iter().enumerate() {
+            assert_eq!(&strategy.decide(health, stats), expected, "caso {}", index);
+        }
+    }
+
+    fn split(strategy: &WeightedSplit, health: &HealthStatusAll, calls: usize) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for _ in 0..calls {
+            let name = match strategy.decide(health, &RoutingStats::default()) {
+                ProcessorDecision::PROCESSOR(name) => name,
+                ProcessorDecision::FAILING => "FAILING".to_string(),
+            };
+            *counts.entry(name).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn weighted_split_follows_the_weights() {
+        let strategy = WeightedSplit::new(processors());
+        let counts = split(&strategy, &health(&[("default", None, 0), ("fallback", None, 0)]), 1_000);
+        assert_eq!(counts, BTreeMap::from([("default".to_string(), 800), ("fallback".to_string(), 200)]));
+    }
+
+    #[test]
+    fn weighted_split_skips_failing_processors() {
+        let strategy = WeightedSplit::new(processors());
+        let counts = split(&strategy, &health(&[("default", Some(1), 0), ("fallback", None, 0)]), 50);
+        assert_eq!(counts, BTreeMap::from([("fallback".to_string(), 50)]));
+
+        let counts = split(&strategy, &health(&[("default", Some(1), 0), ("fallback", Some(1), 0)]), 10);
+        assert_eq!(counts, BTreeMap::from([("FAILING".to_string(), 10)]));
+
+        let unweighted = WeightedSplit::new(vec![processor("default", 0.05, 0), processor("fallback", 0.15, 0)]);
+        let counts = split(&unweighted, &health(&[("default", None, 0), ("fallback", None, 0)]), 10);
+        assert_eq!(counts, BTreeMap::from([("default".to_string(), 10)]));
+    }
+
+    #[test]
+    fn unknown_strategy_falls_back_to_default_first() {
+        assert_eq!(strategy_from_name("priority-first", &processors()).name(), "default-first");
+        assert_eq!(strategy_from_name("qualquer", &processors()).name(), "default-first");
+        assert_eq!(strategy_from_name("weighted-split", &processors()).name(), "weighted-split");
+    }
+}
@@ -0,0 +1,398 @@
+use crate::domain::entities::{ProcessorOutcome, ProcessorStats, RoutingStats};
+use crate::HealthResponse;
+use crate::infrastructure::utils::now_unix_ms;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+const EWMA_ALPHA: f64 = 0.2;
+const LATENCY_SAMPLES: usize = 256;
+const ERROR_WINDOW: usize = 100;
+const MERGED_SAMPLES: usize = 1024;
+// snapshot remoto mais velho que isso é ignorado
+const REMOTE_STATS_TTL_MS: u64 = 5000;
+// mínimo de amostras para confiar no p99 observado
+const MIN_TIMEOUT_SAMPLES: u64 = 20;
+// de quanto em quanto tempo a visão consolidada usada no roteamento é recalculada
+const ROUTING_STATS_REFRESH_MS: u64 = 100;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct StatsSnapshot {
+    #[serde(rename = "ewmaMs")]
+    pub ewma_ms: f64,
+    // latências recentes em ms, usadas para os percentis
+    pub samples: Vec<u64>,
+    // janela deslizante das últimas chamadas
+    #[serde(rename = "windowCalls")]
+    pub window_calls: u64,
+    #[serde(rename = "windowErrors")]
+    pub window_errors: u64,
+}
+
+pub type StatsReport = BTreeMap<String, StatsSnapshot>;
+
+#[derive(Default)]
+struct LatencyTracker {
+    ewma_ms: Option<f64>,
+    samples: VecDeque<u64>,
+    // true = erro
+    outcomes: VecDeque<bool>,
+}
+
+impl LatencyTracker {
+    fn record(&mut self, latency: Duration, outcome: ProcessorOutcome) {
+        // no timeout a latência é o próprio timeout, não a do processador: entrar nos percentis
+        // faria o p99 subir e o próximo timeout crescer junto até o teto. Conta só como erro
+        if outcome != ProcessorOutcome::Timeout {
+            let latency_ms = latency.as_millis() as u64;
+            self.ewma_ms = Some(match self.ewma_ms {
+                Some(ewma) => EWMA_ALPHA * latency_ms as f64 + (1.0 - EWMA_ALPHA) * ewma,
+                None => latency_ms as f64,
+            });
+            self.samples.push_back(latency_ms);
+            if self.samples.len() > LATENCY_SAMPLES {
+                self.samples.pop_front();
+            }
+        }
+        self.outcomes.push_back(outcome.is_error());
+        if self.outcomes.len() > ERROR_WINDOW {
+            self.outcomes.pop_front();
+        }
+    }
+
+    fn snapshot(&self) -> StatsSnapshot {
+        StatsSnapshot {
+            ewma_ms: self.ewma_ms.unwrap_or(0.0),
+            samples: self.samples.iter().copied().collect(),
+            window_calls: self.outcomes.len() as u64,
+            window_errors: self.outcomes.iter().filter(|e| **e).count() as u64,
+        }
+    }
+}
+
+static LOCAL_STATS: Lazy<Mutex<BTreeMap<String, LatencyTracker>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+// snapshots recebidos de outras instâncias (no master: um por slave), com o horário de chegada
+static REMOTE_STATS: Lazy<Mutex<BTreeMap<String, (u64, StatsReport)>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+// visão do cluster já consolidada pelo master (só nos slaves)
+static CLUSTER_STATS: Lazy<Mutex<Option<(u64, StatsReport)>>> = Lazy::new(|| Mutex::new(None));
+// merge + percentis ficam fora do caminho do worker: ele só lê o último resultado
+static ROUTING_STATS: Lazy<RwLock<Arc<RoutingStats>>> = Lazy::new(|| RwLock::new(Arc::new(RoutingStats::default())));
+
+pub fn record_call(processor: &str, latency: Duration, outcome: ProcessorOutcome) {
+    let mut stats = LOCAL_STATS.lock().unwrap();
+    stats.entry(processor.to_string()).or_default().record(latency, outcome);
+}
+
+pub fn local_report() -> StatsReport {
+    LOCAL_STATS.lock().unwrap()
+        .iter()
+        .map(|(processor, tracker)| (processor.clone(), tracker.snapshot()))
+        .collect()
+}
+
+pub fn store_remote_report(source: &str, report: StatsReport) {
+    let now = now_unix_ms();
+    let mut remote = REMOTE_STATS.lock().unwrap();
+    // quem parou de mandar já é ignorado no cluster_report, aqui só libera a memória
+    remote.retain(|_, (received_at, _)| now.saturating_sub(*received_at) <= REMOTE_STATS_TTL_MS);
+    remote.insert(source.to_string(), (now, report));
+}
+
+pub fn remove_remote_report(source: &str) {
+    REMOTE_STATS.lock().unwrap().remove(source);
+}
+
+pub fn store_cluster_report(report: StatsReport) {
+    *CLUSTER_STATS.lock().unwrap() = Some((now_unix_ms(), report));
+}
+
+// local + o que chegou das outras instâncias; no slave usa a visão do master enquanto estiver fresca
+pub fn cluster_report() -> StatsReport {
+    let now = now_unix_ms();
+    if let Some((received_at, report)) = CLUSTER_STATS.lock().unwrap().as_ref() {
+        if now.saturating_sub(*received_at) <= REMOTE_STATS_TTL_MS {
+            return report.clone();
+        }
+    }
+    let mut reports = vec![local_report()];
+    reports.extend(fresh_reports(&REMOTE_STATS.lock().unwrap(), now));
+    merge_reports(&reports)
+}
+
+fn fresh_reports(remote: &BTreeMap<String, (u64, StatsReport)>, now: u64) -> Vec<StatsReport> {
+    remote.values()
+        .filter(|(received_at, _)| now.saturating_sub(*received_at) <= REMOTE_STATS_TTL_MS)
+        .map(|(_, report)| report.clone())
+        .collect()
+}
+
+pub fn merge_reports(reports: &[StatsReport]) -> StatsReport {
+    let mut merged: StatsReport = BTreeMap::new();
+    for report in reports {
+        for (processor, snapshot) in report {
+            let entry = merged.entry(processor.clone()).or_default();
+            let calls = entry.window_calls + snapshot.window_calls;
+            if calls > 0 {
+                entry.ewma_ms = (entry.ewma_ms * entry.window_calls as f64 + snapshot.ewma_ms * snapshot.window_calls as f64) / calls as f64;
+            }
+            entry.window_calls = calls;
+            entry.window_errors += snapshot.window_errors;
+            entry.samples.extend_from_slice(&snapshot.samples);
+        }
+    }
+    for snapshot in merged.values_mut() {
+        if snapshot.samples.len() > MERGED_SAMPLES {
+            let excess = snapshot.samples.len() - MERGED_SAMPLES;
+            snapshot.samples.drain(..excess);
+        }
+    }
+    merged
+}
+
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = ((p / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+    sorted[rank.min(sorted.len() - 1)]
+}
+
+pub fn to_processor_stats(snapshot: &StatsSnapshot) -> ProcessorStats {
+    let mut sorted = snapshot.samples.clone();
+    sorted.sort_unstable();
+    ProcessorStats {
+        calls: snapshot.window_calls,
+        errors: snapshot.window_errors,
+        ewma_ms: snapshot.ewma_ms,
+        p50_ms: percentile(&sorted, 50.0),
+        p95_ms: percentile(&sorted, 95.0),
+        p99_ms: percentile(&sorted, 99.0),
+    }
+}
+
+fn compute_routing_stats() -> RoutingStats {
+    RoutingStats {
+        processors: cluster_report()
+            .iter()
+            .map(|(processor, snapshot)| (processor.clone(), to_processor_stats(snapshot)))
+            .collect(),
+    }
+}
+
+fn refresh_routing_stats() {
+    let stats = Arc::new(compute_routing_stats());
+    *ROUTING_STATS.write().unwrap() = stats;
+}
+
+pub fn start_stats_refresher() {
+    tokio::spawn(async move {
+        loop {
+            refresh_routing_stats();
+            tokio::time::sleep(Duration::from_millis(ROUTING_STATS_REFRESH_MS)).await;
+        }
+    });
+}
+
+// última visão consolidada, recalculada pelo start_stats_refresher
+pub fn processor_stats() -> Arc<RoutingStats> {
+    ROUTING_STATS.read().unwrap().clone()
+}
+
+// timeout da requisição: o maior entre o que o processador informa e o p99 observado, com folga,
+// limitado entre o piso e o teto configurados. Nunca passa do teto: o client HTTP usa o mesmo
+// teto como timeout global e cortaria antes
+pub fn request_timeout(health: &HealthResponse, stats: &ProcessorStats, floor: Duration, ceiling: Duration) -> Duration {
+    let reported_ms = health.min_response_time.max(0) as f64 * 2.0;
+    let observed_ms = if stats.calls >= MIN_TIMEOUT_SAMPLES {
+        stats.p99_ms as f64 * 1.5
+    } else {
+        0.0
+    };
+    let timeout = Duration::from_millis((reported_ms.max(observed_ms) + 50.0) as u64);
+    timeout.clamp(floor.min(ceiling), ceiling)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        fresh_reports, merge_reports, percentile, request_timeout, to_processor_stats, LatencyTracker, StatsReport,
+        StatsSnapshot, ERROR_WINDOW, LATENCY_SAMPLES, MERGED_SAMPLES, MIN_TIMEOUT_SAMPLES, REMOTE_STATS_TTL_MS,
+    };
+    use crate::domain::entities::ProcessorOutcome::{Success, Timeout, TransientFailure};
+    use crate::domain::entities::ProcessorStats;
+    use crate::HealthResponse;
+    use std::collections::BTreeMap;
+    use std::time::Duration;
+
+    fn report(processor: &str, ewma_ms: f64, samples: Vec<u64>, window_calls: u64, window_errors: u64) -> StatsReport {
+        BTreeMap::from([(processor.to_string(), StatsSnapshot { ewma_ms, samples, window_calls, window_errors })])
+    }
+
+    #[test]
+    fn ewma_starts_at_the_first_sample_and_moves_by_alpha() {
+        let mut tracker = LatencyTracker::default();
+        tracker.record(Duration::from_millis(100), Success);
+        assert_eq!(tracker.snapshot().ewma_ms, 100.0);
+
+        tracker.record(Duration::from_millis(200), Success);
+        assert!((tracker.snapshot().ewma_ms - 120.0).abs() < 1e-9);
+        tracker.record(Duration::from_millis(20), Success);
+        assert!((tracker.snapshot().ewma_ms - 100.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn percentile_edges() {
+        assert_eq!(percentile(&[], 50.0), 0);
+        for p in [0.0, 50.0, 99.0, 100.0] {
+            assert_eq!(percentile(&[42], p), 42);
+        }
+
+        let sorted: Vec<u64> = (0..=100).collect();
+        assert_eq!(percentile(&sorted, 0.0), 0);
+        assert_eq!(percentile(&sorted, 50.0), 50);
+        assert_eq!(percentile(&sorted, 99.0), 99);
+        assert_eq!(percentile(&sorted, 100.0), 100);
+        // rank arredondado: com 3 amostras o p95 já é a maior
+        assert_eq!(percentile(&[10, 20, 30], 95.0), 30);
+        assert_eq!(percentile(&[10, 20, 30], 20.0), 10);
+    }
+
+    #[test]
+    fn error_window_and_samples_roll_over() {
+        let mut tracker = LatencyTracker::default();
+        for _ in 0..ERROR_WINDOW {
+            tracker.record(Duration::from_millis(1), TransientFailure);
+        }
+        let snapshot = tracker.snapshot();
+        assert_eq!((snapshot.window_calls, snapshot.window_errors), (ERROR_WINDOW as u64, ERROR_WINDOW as u64));
+
+        // cada sucesso empurra um erro antigo para fora da janela
+        for _ in 0..ERROR_WINDOW / 2 {
+            tracker.record(Duration::from_millis(1), Success);
+        }
+        let snapshot = tracker.snapshot();
+        assert_eq!((snapshot.window_calls, snapshot.window_errors), (ERROR_WINDOW as u64, (ERROR_WINDOW / 2) as u64));
+
+        for _ in 0..ERROR_WINDOW {
+            tracker.record(Duration::from_millis(1), Success);
+        }
+        assert_eq!(tracker.snapshot().window_errors, 0);
+
+        for latency in 0..(LATENCY_SAMPLES as u64 + 10) {
+            tracker.record(Duration::from_millis(latency), Success);
+        }
+        let samples = tracker.snapshot().samples;
+        assert_eq!(samples.len(), LATENCY_SAMPLES);
+        assert_eq!(samples.first(), Some(&10));
+    }
+
+    #[test]
+    fn merge_weights_ewma_by_calls_and_sums_windows() {
+        let merged = merge_reports(&[
+            report("default", 100.0, vec![1, 2], 1, 0),
+            report("default", 200.0, vec![3], 3, 2),
+            report("fallback", 50.0, vec![4], 2, 1),
+            // sem chamadas na janela não pesa no ewma
+            report("fallback", 999.0, vec![], 0, 0),
+        ]);
+
+        let default = &merged["default"];
+        assert!((default.ewma_ms - 175.0).abs() < 1e-9);
+        assert_eq!((default.window_calls, default.window_errors), (4, 2));
+        assert_eq!(default.samples, vec![1, 2, 3]);
+
+        let fallback = &merged["fallback"];
+        assert_eq!(fallback.ewma_ms, 50.0);
+        assert_eq!((fallback.window_calls, fallback.window_errors), (2, 1));
+    }
+
+    #[test]
+    fn merge_keeps_only_the_newest_samples() {
+        let older: Vec<u64> = vec![0; MERGED_SAMPLES];
+        let newer: Vec<u64> = vec![7; 10];
+        let merged = merge_reports(&[report("default", 1.0, older, 1, 0), report("default", 1.0, newer, 1, 0)]);
+        let samples = &merged["default"].samples;
+        assert_eq!(samples.len(), MERGED_SAMPLES);
+        assert_eq!(&samples[MERGED_SAMPLES - 10..], &[7; 10]);
+    }
+
+    #[test]
+    fn expired_reports_are_left_out_of_the_merge() {
+        let now = 100_000;
+        let remote = BTreeMap::from([
+            ("api01".to_string(), (now - REMOTE_STATS_TTL_MS, report("default", 300.0, vec![300], 1, 1))),
+            ("api02".to_string(), (now - REMOTE_STATS_TTL_MS - 1, report("default", 900.0, vec![900], 9, 9))),
+        ]);
+
+        let mut reports = vec![report("default", 100.0, vec![100], 1, 0)];
+        reports.extend(fresh_reports(&remote, now));
+        let merged = merge_reports(&reports);
+        let default = &merged["default"];
+        assert!((default.ewma_ms - 200.0).abs() < 1e-9);
+        assert_eq!((default.window_calls, default.window_errors), (2, 1));
+        assert_eq!(default.samples, vec![100, 300]);
+    }
+
+    const FLOOR: Duration = Duration::from_millis(100);
+    const CEILING: Duration = Duration::from_millis(2000);
+
+    fn health(min_response_time: i64) -> HealthResponse {
+        HealthResponse { failing: false, min_response_time, failing_since: None }
+    }
+
+    fn observed(calls: u64, p99_ms: u64) -> ProcessorStats {
+        ProcessorStats { calls, p99_ms, ..Default::default() }
+    }
+
+    #[test]
+    fn timeout_falls_back_to_min_response_time_without_samples() {
+        // 2x o minResponseTime + 50ms de folga
+        assert_eq!(request_timeout(&health(300), &ProcessorStats::default(), FLOOR, CEILING), Duration::from_millis(650));
+        // poucas chamadas: o p99 ainda não é confiável
+        let few = observed(MIN_TIMEOUT_SAMPLES - 1, 1500);
+        assert_eq!(request_timeout(&health(300), &few, FLOOR, CEILING), Duration::from_millis(650));
+    }
+
+    #[test]
+    fn timeout_uses_the_observed_p99_when_larger() {
+        let stats = observed(MIN_TIMEOUT_SAMPLES, 800);
+        // 1.5x o p99 + 50ms
+        assert_eq!(request_timeout(&health(300), &stats, FLOOR, CEILING), Duration::from_millis(1250));
+        // o minResponseTime informado ganha quando é maior
+        assert_eq!(request_timeout(&health(700), &stats, FLOOR, CEILING), Duration::from_millis(1450));
+    }
+
+    #[test]
+    fn timeout_is_clamped_between_floor_and_ceiling() {
+        assert_eq!(request_timeout(&health(0), &ProcessorStats::default(), FLOOR, CEILING), FLOOR);
+        assert_eq!(request_timeout(&health(-10), &ProcessorStats::default(), FLOOR, CEILING), FLOOR);
+        assert_eq!(request_timeout(&health(5000), &ProcessorStats::default(), FLOOR, CEILING), CEILING);
+        assert_eq!(request_timeout(&health(0), &observed(100, 10_000), FLOOR, CEILING), CEILING);
+        // piso acima do teto: o teto vale, é o timeout global do client
+        let high_floor = Duration::from_millis(3000);
+        assert_eq!(request_timeout(&health(0), &ProcessorStats::default(), high_floor, CEILING), CEILING);
+    }
+
+    #[test]
+    fn repeated_timeouts_do_not_raise_the_timeout() {
+        let mut tracker = LatencyTracker::default();
+        for _ in 0..MIN_TIMEOUT_SAMPLES {
+            tracker.record(Duration::from_millis(200), Success);
+        }
+        let before = to_processor_stats(&tracker.snapshot());
+        let timeout = request_timeout(&health(100), &before, FLOOR, CEILING);
+        assert_eq!(timeout, Duration::from_millis(350));
+
+        // cada timeout dura o próprio timeout; se entrasse no p99 o próximo seria maior
+        for _ in 0..ERROR_WINDOW {
+            tracker.record(timeout, Timeout);
+        }
+        let snapshot = tracker.snapshot();
+        assert_eq!(snapshot.samples.len(), MIN_TIMEOUT_SAMPLES as usize);
+        assert_eq!(snapshot.ewma_ms, 200.0);
+        assert_eq!((snapshot.window_calls, snapshot.window_errors), (ERROR_WINDOW as u64, ERROR_WINDOW as u64));
+        assert_eq!(request_timeout(&health(100), &to_processor_stats(&snapshot), FLOOR, CEILING), timeout);
+    }
+}
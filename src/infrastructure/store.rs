@@ -0,0 +1,207 @@
+use crate::domain::entities::{DeadLetter, PaymentRecord};
+use crate::domain::money::Money;
+use crate::domain::queue::PaymentQueue;
+use crate::domain::store::{PaymentStore, StoreResult, SummarySeries};
+use crate::infrastructure::config::PAYMENT_STORE_NAME;
+use crate::infrastructure::memory::{MemoryPaymentQueue, MemoryPaymentStore};
+use crate::infrastructure::queue;
+use crate::infrastructure::redis::{
+    get_redis_connection, is_recorded, lookup_payment, payments_in_range, pin_processor, purge_payments, release_processor,
+    store_summary, summary_series, summary_totals,
+};
+use futures::future::BoxFuture;
+use redis::aio::ConnectionManager;
+use std::sync::Arc;
+use std::time::Duration;
+
+// onde ficam os pagamentos e a fila; `redis` só existe quando o backend é o redis, e é ele que
+// também sustenta a eleição de líder e a distribuição do health entre as instâncias
+pub struct Backend {
+    pub store: Arc<dyn PaymentStore>,
+    pub queue: Arc<dyn PaymentQueue>,
+    pub redis: Option<ConnectionManager>,
+}
+
+pub async fn open_backend() -> Result<Backend, String> {
+    let backend = match PAYMENT_STORE_NAME.as_str() {
+        "memory" => Backend {
+            store: Arc::new(MemoryPaymentStore::new()),
+            queue: Arc::new(MemoryPaymentQueue::new()),
+            redis: None,
+        },
+        other => {
+            if other != "redis" {
+                eprintln!("[STORE] Armazenamento desconhecido {}, usando redis", other);
+            }
+            let conn = get_redis_connection().await.map_err(|e| format!("Falha ao conectar no redis: {:?}", e))?;
+            Backend {
+                store: Arc::new(RedisPaymentStore::new(conn.clone())),
+                queue: Arc::new(RedisPaymentQueue::new(conn.clone())),
+                redis: Some(conn),
+            }
+        }
+    };
+    println!("[STORE] Pagamentos e fila em: {}", backend.store.name());
+    Ok(backend)
+}
+
+// compartilhado entre as instâncias: o summary de qualquer uma enxerga todos os pagamentos
+pub struct RedisPaymentStore {
+    conn: ConnectionManager,
+}
+
+impl RedisPaymentStore {
+    pub fn new(conn: ConnectionManager) -> RedisPaymentStore {
+        RedisPaymentStore { conn }
+    }
+}
+
+impl PaymentStore for RedisPaymentStore {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    fn record<'a>(&'a self, processor: &'a str, id: &'a str, amount: Money, timestamp_ms: i64) -> BoxFuture<'a, StoreResult<bool>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move {
+            store_summary(&mut conn, processor, id, amount, timestamp_ms).await.map_err(|e| e.to_string())
+        })
+    }
+
+    fn is_recorded<'a>(&'a self, id: &'a str) -> BoxFuture<'a, StoreResult<bool>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { is_recorded(&mut conn, id).await.map_err(|e| e.to_string()) })
+    }
+
+    fn lookup<'a>(&'a self, id: &'a str) -> BoxFuture<'a, StoreResult<Option<PaymentRecord>>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { lookup_payment(&mut conn, id).await.map_err(|e| e.to_string()) })
+    }
+
+    fn query_range<'a>(&'a self, processor: &'a str, from: i64, to: i64) -> BoxFuture<'a, StoreResult<Vec<PaymentRecord>>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { payments_in_range(&mut conn, processor, from, to).await.map_err(|e| e.to_string()) })
+    }
+
+    fn summary<'a>(&'a self, processors: &'a [&'a str], from: i64, to: i64) -> BoxFuture<'a, StoreResult<Vec<(u64, Money)>>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { summary_totals(&mut conn, processors, from, to).await.map_err(|e| e.to_string()) })
+    }
+
+    fn series<'a>(&'a self, processors: &'a [&'a str], from: i64, to: i64, interval: i64) -> BoxFuture<'a, StoreResult<SummarySeries>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move {
+            summary_series(&mut conn, processors, from, to, interval).await.map_err(|e| e.to_string())
+        })
+    }
+
+    fn pin_processor<'a>(&'a self, id: &'a str, processor: &'a str) -> BoxFuture<'a, StoreResult<String>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { pin_processor(&mut conn, id, processor).await.map_err(|e| e.to_string()) })
+    }
+
+    fn release_processor<'a>(&'a self, id: &'a str, processor: &'a str) -> BoxFuture<'a, StoreResult<()>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { release_processor(&mut conn, id, processor).await.map_err(|e| e.to_string()) })
+    }
+
+    fn purge<'a>(&'a self, processors: &'a [&'a str]) -> BoxFuture<'a, StoreResult<()>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { purge_payments(&mut conn, processors).await.map_err(|e| e.to_string()) })
+    }
+}
+
+// lista no redis compartilhada pelas instâncias; em processamento fica uma lista por instância
+pub struct RedisPaymentQueue {
+    conn: ConnectionManager,
+}
+
+impl RedisPaymentQueue {
+    pub fn new(conn: ConnectionManager) -> RedisPaymentQueue {
+        RedisPaymentQueue { conn }
+    }
+}
+
+impl PaymentQueue for RedisPaymentQueue {
+    fn name(&self) -> &'static str {
+        "redis"
+    }
+
+    fn for_worker(&self) -> BoxFuture<'_, StoreResult<Box<dyn PaymentQueue>>> {
+        Box::pin(async move {
+            let conn = get_redis_connection().await.map_err(|e| e.to_string())?;
+            Ok(Box::new(RedisPaymentQueue::new(conn)) as Box<dyn PaymentQueue>)
+        })
+    }
+
+    fn enqueue<'a>(&'a self, correlation_id: &'a str, payload: &'a [u8]) -> BoxFuture<'a, StoreResult<bool>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { queue::enqueue_payment(&mut conn, correlation_id, payload).await.map_err(|e| e.to_string()) })
+    }
+
+    fn purge_accepted(&self) -> BoxFuture<'_, StoreResult<()>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { queue::purge_accepted(&mut conn).await.map_err(|e| e.to_string()) })
+    }
+
+    fn claim(&self, timeout: Duration) -> BoxFuture<'_, StoreResult<Option<Vec<u8>>>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { queue::claim_payment(&mut conn, timeout.as_secs_f64()).await.map_err(|e| e.to_string()) })
+    }
+
+    fn ack<'a>(&'a self, claimed: &'a [u8]) -> BoxFuture<'a, StoreResult<()>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { queue::ack_payment(&mut conn, claimed).await.map_err(|e| e.to_string()) })
+    }
+
+    fn in_flight(&self) -> BoxFuture<'_, StoreResult<u64>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { queue::in_flight_count(&mut conn).await.map_err(|e| e.to_string()) })
+    }
+
+    fn recover_in_flight(&self) -> BoxFuture<'_, StoreResult<usize>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { queue::recover_in_flight(&mut conn).await.map_err(|e| e.to_string()) })
+    }
+
+    fn schedule_retry<'a>(&'a self, claimed: &'a [u8], payload: &'a [u8], due_ms: u64) -> BoxFuture<'a, StoreResult<()>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { queue::schedule_retry(&mut conn, claimed, payload, due_ms).await.map_err(|e| e.to_string()) })
+    }
+
+    fn promote_due(&self, now_ms: u64, limit: usize) -> BoxFuture<'_, StoreResult<usize>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { queue::promote_due(&mut conn, now_ms, limit).await.map_err(|e| e.to_string()) })
+    }
+
+    fn dead_letter<'a>(&'a self, claimed: &'a [u8], entry: &'a DeadLetter) -> BoxFuture<'a, StoreResult<()>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { queue::dead_letter_payment(&mut conn, claimed, entry).await.map_err(|e| e.to_string()) })
+    }
+
+    fn list_dead_letters(&self) -> BoxFuture<'_, StoreResult<Vec<DeadLetter>>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { queue::list_dead_letters(&mut conn).await.map_err(|e| e.to_string()) })
+    }
+
+    fn get_dead_letter<'a>(&'a self, correlation_id: &'a str) -> BoxFuture<'a, StoreResult<Option<DeadLetter>>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { queue::get_dead_letter(&mut conn, correlation_id).await.map_err(|e| e.to_string()) })
+    }
+
+    fn replay_dead_letter(&self, entry: DeadLetter) -> BoxFuture<'_, StoreResult<bool>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { queue::replay_dead_letter(&mut conn, entry).await.map_err(|e| e.to_string()) })
+    }
+
+    fn discard_dead_letter<'a>(&'a self, correlation_id: &'a str) -> BoxFuture<'a, StoreResult<bool>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { queue::discard_dead_letter(&mut conn, correlation_id).await.map_err(|e| e.to_string()) })
+    }
+
+    fn discard_all_dead_letters(&self) -> BoxFuture<'_, StoreResult<usize>> {
+        let mut conn = self.conn.clone();
+        Box::pin(async move { queue::discard_all_dead_letters(&mut conn).await.map_err(|e| e.to_string()) })
+    }
+}
+
@@ -0,0 +1,275 @@
+use crate::domain::entities::HealthStatusAll;
+use crate::domain::retry::RetryPolicy;
+use crate::infrastructure::circuit_breaker::circuit_breaker;
+use crate::infrastructure::config::{
+    GLOBAL_HEALTH_STATUS, HEALTH_SNAPSHOT_KEY, HEALTH_SNAPSHOT_TTL_MS, INSTANCE_ID, WS_RECONNECT_BASE_MS,
+    WS_RECONNECT_MAX_MS,
+};
+use crate::infrastructure::health::HealthEvent;
+use crate::infrastructure::metrics::{
+    local_outcomes, remove_instance_metrics, store_instance_outcomes, store_instance_queue_depth, OutcomeCounters,
+};
+use crate::infrastructure::queue::in_flight_count;
+use crate::infrastructure::stats::{
+    cluster_report, local_report, remove_remote_report, store_cluster_report, store_remote_report, StatsReport,
+};
+use crate::infrastructure::utils::now_unix_ms;
+use crate::infrastructure::ws_auth::{check_freshness, open_frame, seal_frame};
+use crate::HealthResponse;
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, RedisResult};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// protocolo de sincronização entre o líder e as outras instâncias, independente do transporte
+
+// muda quando o formato das mensagens deixar de ser compatível
+pub const PROTOCOL_VERSION: u32 = 2;
+
+#[derive(Deserialize, Serialize, Debug)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum SyncMessage {
+    // líder -> slave: estado completo, fallback caso algum delta se perca
+    Snapshot {
+        health: HealthStatusAll,
+    },
+    // líder -> slave: um processador mudou no último health check
+    HealthDelta {
+        processor: String,
+        health: HealthResponse,
+        #[serde(rename = "updatedAt")]
+        updated_at: u64,
+    },
+    // nos dois sentidos: o slave manda as dele, o líder devolve as do cluster
+    Stats {
+        stats: StatsReport,
+    },
+    // slave -> líder
+    Outcomes {
+        outcomes: OutcomeCounters,
+    },
+    // slave -> líder
+    QueueDepth {
+        #[serde(rename = "inFlight")]
+        in_flight: u64,
+    },
+}
+
+#[derive(Deserialize, Serialize, Debug)]
+pub struct Envelope {
+    pub version: u32,
+    pub sender: String,
+    #[serde(rename = "sentAt")]
+    pub sent_at: u64,
+    #[serde(flatten)]
+    pub message: SyncMessage,
+}
+
+pub fn encode(message: SyncMessage) -> String {
+    let envelope = Envelope {
+        version: PROTOCOL_VERSION,
+        sender: INSTANCE_ID.to_string(),
+        sent_at: now_unix_ms(),
+        message,
+    };
+    seal_frame(serde_json::to_string(&envelope).unwrap())
+}
+
+pub fn decode(frame: &str) -> Result<Envelope, String> {
+    let json = open_frame(frame)?;
+    let envelope = serde_json::from_str::<Envelope>(json).map_err(|e| e.to_string())?;
+    if envelope.version != PROTOCOL_VERSION {
+        return Err(format!("versão de protocolo {} não suportada (esperada {})", envelope.version, PROTOCOL_VERSION));
+    }
+    check_freshness(envelope.sent_at)?;
+    Ok(envelope)
+}
+
+pub async fn snapshot_frames() -> Vec<String> {
+    let health = GLOBAL_HEALTH_STATUS.read().await.clone();
+    vec![
+        encode(SyncMessage::Snapshot { health }),
+        encode(SyncMessage::Stats { stats: cluster_report() }),
+    ]
+}
+
+pub fn delta_frame(event: HealthEvent) -> String {
+    encode(SyncMessage::HealthDelta {
+        processor: event.processor,
+        health: event.health,
+        updated_at: event.updated_at,
+    })
+}
+
+// o que um slave manda periodicamente para o líder
+pub async fn report_frames(conn: &mut ConnectionManager) -> Vec<String> {
+    let mut frames = vec![
+        encode(SyncMessage::Stats { stats: local_report() }),
+        encode(SyncMessage::Outcomes { outcomes: local_outcomes() }),
+    ];
+    match in_flight_count(conn).await {
+        Ok(in_flight) => frames.push(encode(SyncMessage::QueueDepth { in_flight })),
+        Err(e) => eprintln!("[SYNC] Erro ao medir a fila em processamento: {:?}", e),
+    }
+    frames
+}
+
+// erro quando a mensagem foi recusada (assinatura, versão ou idade) ou não é do líder
+pub async fn apply_leader_frame(frame: &str) -> Result<(), String> {
+    let envelope = decode(frame).map_err(|err| format!("mensagem inválida do líder: {err}"))?;
+    match envelope.message {
+        SyncMessage::Snapshot { health } => {
+            for (processor, processor_health) in &health.processors {
+                circuit_breaker(processor).record_health(processor_health.failing);
+            }
+            let mut guard = GLOBAL_HEALTH_STATUS.write().await;
+            // o carimbo é do líder: dado repassado não fica "novo" só por ter chegado agora
+            guard.updated_at = guard.updated_at.max(health.updated_at);
+            guard.processors.extend(health.processors);
+        }
+        SyncMessage::HealthDelta { processor, health, updated_at } => {
+            circuit_breaker(&processor).record_health(health.failing);
+            let mut guard = GLOBAL_HEALTH_STATUS.write().await;
+            guard.updated_at = guard.updated_at.max(updated_at);
+            guard.processors.insert(processor, health);
+        }
+        SyncMessage::Stats { stats } => store_cluster_report(stats),
+        other => return Err(format!("mensagem inesperada do líder: {:?}", other)),
+    }
+    Ok(())
+}
+
+// retorna quem mandou, para o transporte saber de quem limpar os dados quando a instância sumir
+pub fn apply_follower_frame(frame: &str) -> Option<String> {
+    let envelope = match decode(frame) {
+        Ok(envelope) => envelope,
+        Err(err) => {
+            eprintln!("[SYNC] Mensagem inválida de slave: {err}");
+            return None;
+        }
+    };
+    match envelope.message {
+        SyncMessage::Stats { stats } => store_remote_report(&envelope.sender, stats),
+        SyncMessage::Outcomes { outcomes } => store_instance_outcomes(&envelope.sender, outcomes),
+        SyncMessage::QueueDepth { in_flight } => store_instance_queue_depth(&envelope.sender, in_flight),
+        other => eprintln!("[SYNC] Mensagem inesperada de {}: {:?}", envelope.sender, other),
+    }
+    Some(envelope.sender)
+}
+
+pub fn forget_follower(sender: &str) {
+    remove_remote_report(sender);
+    remove_instance_metrics(sender);
+}
+
+// último snapshot fica no redis com TTL: quem acabou de subir já começa com o health do cluster
+pub async fn store_snapshot(conn: &mut ConnectionManager) -> RedisResult<()> {
+    let health = GLOBAL_HEALTH_STATUS.read().await.clone();
+    let frame = encode(SyncMessage::Snapshot { health });
+    conn.pset_ex(HEALTH_SNAPSHOT_KEY, frame, *HEALTH_SNAPSHOT_TTL_MS).await
+}
+
+// true só quando havia snapshot e ele foi aplicado
+pub async fn bootstrap_from_snapshot(conn: &mut ConnectionManager) -> Result<bool, String> {
+    let frame: Option<String> = conn.get(HEALTH_SNAPSHOT_KEY).await.map_err(|e| e.to_string())?;
+    match frame {
+        Some(frame) => apply_leader_frame(&frame).await.map(|_| true),
+        None => Ok(false),
+    }
+}
+
+// mesmo backoff com jitter das retentativas de pagamento, sem limite de tentativas
+pub fn reconnect_policy() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: u32::MAX,
+        base_delay: Duration::from_millis(*WS_RECONNECT_BASE_MS),
+        max_delay: Duration::from_millis(*WS_RECONNECT_MAX_MS),
+        jitter: 0.2,
+        deadline: Duration::MAX,
+        retryable: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, Envelope, SyncMessage, PROTOCOL_VERSION};
+    use crate::domain::entities::HealthStatusAll;
+    use crate::infrastructure::stats::StatsSnapshot;
+    use crate::infrastructure::utils::now_unix_ms;
+    use crate::HealthResponse;
+    use std::collections::BTreeMap;
+
+    fn health(failing: bool, min_response_time: i64) -> HealthResponse {
+        HealthResponse { failing, min_response_time, failing_since: failing.then_some(1_000) }
+    }
+
+    // a mensagem volta igual depois de passar pelo envelope (sem WS_SHARED_SECRET os frames não são assinados)
+    fn round_trip(message: SyncMessage) -> SyncMessage {
+        let expected = serde_json::to_value(&message).unwrap();
+        let envelope = decode(&encode(message)).unwrap();
+        assert_eq!(envelope.version, PROTOCOL_VERSION);
+        assert_eq!(serde_json::to_value(&envelope.message).unwrap(), expected);
+        envelope.message
+    }
+
+    #[test]
+    fn snapshot_keeps_every_processor_and_updated_at() {
+        let processors = BTreeMap::from([
+            ("default".to_string(), health(false, 10)),
+            ("fallback".to_string(), health(true, 250)),
+        ]);
+        let message = round_trip(SyncMessage::Snapshot { health: HealthStatusAll { processors, updated_at: 42 } });
+        let SyncMessage::Snapshot { health } = message else {
+            panic!("esperava snapshot, veio {:?}", message);
+        };
+        assert_eq!(health.updated_at, 42);
+        assert_eq!(health.processors.len(), 2);
+        assert!(health.processors["fallback"].failing);
+        assert_eq!(health.processors["fallback"].failing_since, Some(1_000));
+        assert_eq!(health.processors["default"].min_response_time, 10);
+    }
+
+    #[test]
+    fn every_message_type_round_trips() {
+        let message = round_trip(SyncMessage::HealthDelta { processor: "default".to_string(), health: health(true, 5), updated_at: 7 });
+        assert!(matches!(message, SyncMessage::HealthDelta { updated_at: 7, .. }));
+
+        let stats = BTreeMap::from([("default".to_string(), StatsSnapshot { ewma_ms: 12.5, samples: vec![10, 15], ..Default::default() })]);
+        let message = round_trip(SyncMessage::Stats { stats });
+        assert!(matches!(message, SyncMessage::Stats { stats } if stats["default"].samples == [10, 15]));
+
+        let outcomes = BTreeMap::from([("default".to_string(), BTreeMap::from([("success".to_string(), 3)]))]);
+        round_trip(SyncMessage::Outcomes { outcomes });
+
+        let message = round_trip(SyncMessage::QueueDepth { in_flight: 9 });
+        assert!(matches!(message, SyncMessage::QueueDepth { in_flight: 9 }));
+    }
+
+    #[test]
+    fn frame_is_a_flat_envelope() {
+        let frame: serde_json::Value = serde_json::from_str(&encode(SyncMessage::QueueDepth { in_flight: 1 })).unwrap();
+        assert_eq!(frame["version"], PROTOCOL_VERSION);
+        assert_eq!(frame["type"], "queueDepth");
+        assert_eq!(frame["inFlight"], 1);
+    }
+
+    #[test]
+    fn other_protocol_versions_are_rejected() {
+        for version in [PROTOCOL_VERSION - 1, PROTOCOL_VERSION + 1] {
+            let envelope = Envelope {
+                version,
+                sender: "api-2".to_string(),
+                sent_at: now_unix_ms(),
+                message: SyncMessage::QueueDepth { in_flight: 1 },
+            };
+            let err = decode(&serde_json::to_string(&envelope).unwrap()).unwrap_err();
+            assert!(err.contains("versão de protocolo"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn malformed_frames_are_rejected() {
+        assert!(decode("não é json").is_err());
+        assert!(decode(&format!(r#"{{"version":{},"sender":"x","sentAt":1,"type":"desconhecido"}}"#, PROTOCOL_VERSION)).is_err());
+    }
+}
@@ -1,25 +1,17 @@
-use chrono::{DateTime, NaiveDateTime};
+use std::time::{SystemTime, UNIX_EPOCH};
 
-pub fn date_to_ts(date: String) -> f64 {
-    if let Ok(dt) = DateTime::parse_from_rfc3339(&*date) {
-        //println!("parse_from_rfc3339 {}\n", date);
-        return dt.timestamp_millis() as f64;
-    }
-    //println!("parse_from_str {}\n", date);
-    let naive = NaiveDateTime::parse_from_str(&*date, "%Y-%m-%dT%H:%M:%S").unwrap();
-    naive.and_utc().timestamp_millis() as f64
-}
-
-pub fn round2(val: f64) -> f64 {
-    let rounded = (val * 100.0).round() / 100.0;
-    sanitize_zero(rounded)
+pub fn now_unix_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
 }
 
-//remove os -0.0 para ficar 0.0
-fn sanitize_zero(val: f64) -> f64 {
-    if val == 0.0 {
-        0.0
+pub fn elapsed_since(timestamp_ms: u64) -> Option<std::time::Duration> {
+    let now = now_unix_ms();
+    if now > timestamp_ms {
+        Some(std::time::Duration::from_millis(now - timestamp_ms))
     } else {
-        val
+        None
     }
 }
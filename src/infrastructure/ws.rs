@@ -1,85 +1,225 @@
 use futures::{SinkExt, StreamExt};
+use redis::aio::ConnectionManager;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::net::TcpListener;
-use tokio_tungstenite::{accept_async, connect_async};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_tungstenite::{accept_hdr_async, connect_async_tls_with_config};
+use tungstenite::client::IntoClientRequest;
 use tungstenite::{Message};
-use crate::domain::entities::HealthStatusAll;
-use crate::HealthResponse;
-use crate::infrastructure::config::{GLOBAL_HEALTH_STATUS, WS_MASTER_URL};
+use crate::infrastructure::config::{
+    LEADER_RENEW_MS, WS_HEARTBEAT_TIMEOUT_MS, WS_PING_INTERVAL_MS, WS_RECONNECT_MAX_MS, WS_REPORT_INTERVAL_MS,
+    WS_SNAPSHOT_INTERVAL_MS,
+};
+use crate::infrastructure::health::subscribe_health_events;
+use crate::infrastructure::leader::{current_leader, is_leader, Lease};
+use crate::infrastructure::sync::{
+    apply_follower_frame, apply_leader_frame, delta_frame, forget_follower, reconnect_policy, report_frames,
+    snapshot_frames,
+};
+use crate::infrastructure::utils::now_unix_ms;
+use crate::infrastructure::ws_auth::{sign_handshake, verify_handshake, TLS_ACCEPTOR, TLS_CONNECTOR};
 
-pub async fn run_master() {
+// todas as instâncias escutam, mas só o líder mantém conexões abertas
+pub fn run_master() {
     tokio::spawn(async move {
-        let listener = TcpListener::bind("0.0.0.0:9001").await.unwrap();
-        println!("[MASTER] WebSocket escutando em ws://0.0.0.0:9001");
+        // porta ocupada não pode derrubar a API: tenta de novo até conseguir
+        let listener = loop {
+            match TcpListener::bind("0.0.0.0:9001").await {
+                Ok(listener) => break listener,
+                Err(e) => {
+                    eprintln!("[MASTER] Não foi possível escutar em 0.0.0.0:9001: {e}");
+                    tokio::time::sleep(Duration::from_millis(*WS_RECONNECT_MAX_MS)).await;
+                }
+            }
+        };
+        let scheme = if TLS_ACCEPTOR.is_some() { "wss" } else { "ws" };
+        println!("[MASTER] WebSocket escutando em {scheme}://0.0.0.0:9001");
 
-        while let Ok((stream, _)) = listener.accept().await {
+        while let Ok((stream, addr)) = listener.accept().await {
             println!("[MASTER] Conexão recebida");
             tokio::spawn(async move {
-                let ws_stream = accept_async(stream).await.unwrap();
-                let (mut write, _read) = ws_stream.split();
-                // envio periódico de dados
-                loop {
-                    let global_status = GLOBAL_HEALTH_STATUS.read().await;
-                    let data = HealthStatusAll {
-                        default: HealthResponse {
-                            failing: global_status.default.failing,
-                            min_response_time: global_status.default.min_response_time,
-                            failing_since: global_status.default.failing_since
-                        },
-                        fallback: HealthResponse {
-                            failing: global_status.fallback.failing,
-                            min_response_time: global_status.fallback.min_response_time,
-                            failing_since: global_status.fallback.failing_since
-                        },
-                    };
-                    let json = serde_json::to_string(&data).unwrap();
-                    let _ = write.send(Message::text(json)).await;
-                    tokio::time::sleep(tokio::time::Duration::from_millis(2000)).await;
+                match TLS_ACCEPTOR.as_ref() {
+                    Some(acceptor) => match acceptor.accept(stream).await {
+                        Ok(stream) => serve_follower(stream, addr).await,
+                        Err(e) => eprintln!("[MASTER] Falha no TLS com {addr}: {e}"),
+                    },
+                    None => serve_follower(stream, addr).await,
                 }
             });
         }
     });
 }
 
-pub async fn run_slave() {
-    tokio::spawn(async move {
-        //let url = url::Url::parse(WS_MASTER_URL.as_str()).unwrap();
-        match connect_async(WS_MASTER_URL.as_str()).await {
-            Ok((ws_stream, _)) => {
-                println!("[CLIENTE] Conectado com sucesso!");
-                let (_write, mut read) = ws_stream.split();
+async fn serve_follower<S>(stream: S, addr: std::net::SocketAddr)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    // handshake sem assinatura válida (ou de origem não permitida) é recusado antes de virar WebSocket
+    let ws_stream = match accept_hdr_async(stream, verify_handshake).await {
+        Ok(ws_stream) => ws_stream,
+        Err(e) => {
+            eprintln!("[MASTER] Falha no handshake com {addr}: {e}");
+            return;
+        }
+    };
+    let (mut write, mut read) = ws_stream.split();
+    let last_seen = Arc::new(AtomicU64::new(now_unix_ms()));
+    // até a primeira mensagem, a instância é identificada pelo endereço
+    let source = Arc::new(Mutex::new(addr.to_string()));
 
-                while let Some(msg) = read.next().await {
-                    match msg {
-                        Ok(msg) => {
-                            //println!("{}", msg.clone());
-                            if msg.is_text() {
-                                let json = msg.to_text().unwrap();
-                                match serde_json::from_str::<HealthStatusAll>(json) {
-                                    Ok(data) => {
-                                        //let debug_data = data.clone();
-                                        //println!("default: {:?}, fallback: {:?}", debug_data.default, debug_data.fallback);
-                                        GLOBAL_HEALTH_STATUS.write().await.default = data.default;
-                                        GLOBAL_HEALTH_STATUS.write().await.fallback = data.fallback;
+    // o slave manda estatísticas, outcomes e fila; qualquer mensagem (inclusive ping) conta como sinal de vida
+    let reader_last_seen = Arc::clone(&last_seen);
+    let reader_source = Arc::clone(&source);
+    let reader = tokio::spawn(async move {
+        while let Some(Ok(msg)) = read.next().await {
+            reader_last_seen.store(now_unix_ms(), Ordering::Relaxed);
+            if !msg.is_text() {
+                continue;
+            }
+            if let Some(sender) = apply_follower_frame(msg.to_text().unwrap()) {
+                *reader_source.lock().unwrap() = sender;
+            }
+        }
+    });
 
-                                    }
-                                    Err(err) => {
-                                        eprintln!("[CLIENTE] Erro ao parsear JSON: {err}");
-                                    }
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            eprintln!("[CLIENTE] Erro na conexão: {e}");
-                            break;
-                        }
+    let mut events = subscribe_health_events();
+    let mut snapshot = tokio::time::interval(Duration::from_millis(*WS_SNAPSHOT_INTERVAL_MS));
+    // se perder a liderança, fecha e o slave procura o novo líder
+    'connection: while is_leader() {
+        if now_unix_ms().saturating_sub(last_seen.load(Ordering::Relaxed)) > *WS_HEARTBEAT_TIMEOUT_MS {
+            eprintln!("[MASTER] {} sem heartbeat, encerrando conexão", source.lock().unwrap());
+            break;
+        }
+        let frames = tokio::select! {
+            _ = snapshot.tick() => snapshot_frames().await,
+            event = events.recv() => match event {
+                Ok(event) => vec![delta_frame(event)],
+                // perdeu deltas: manda o estado completo
+                Err(RecvError::Lagged(_)) => snapshot_frames().await,
+                Err(RecvError::Closed) => break,
+            },
+        };
+        for frame in frames {
+            if write.send(Message::text(frame)).await.is_err() {
+                break 'connection;
+            }
+        }
+    }
+
+    reader.abort();
+    let source = source.lock().unwrap().clone();
+    forget_follower(&source);
+    println!("[MASTER] Conexão encerrada");
+}
+
+pub fn run_slave(conn: ConnectionManager) {
+    tokio::spawn(async move {
+        let mut conn = conn;
+        let policy = reconnect_policy();
+        let mut failures: u32 = 0;
+        loop {
+            let leader = match current_leader(&mut conn).await {
+                Ok(leader) => leader,
+                Err(e) => {
+                    eprintln!("[CLIENTE] Erro ao consultar o líder: {:?}", e);
+                    None
+                }
+            };
+            let delay = match leader {
+                // o líder é esse processo (ou ainda não tem líder): não há o que sincronizar
+                Some(lease) if !is_leader() && !lease.is_own() => {
+                    if follow_leader(&mut conn, &lease).await {
+                        failures = 0;
+                        policy.base_delay
+                    } else {
+                        failures = failures.saturating_add(1);
+                        policy.delay(failures)
                     }
                 }
+                _ => Duration::from_millis(*LEADER_RENEW_MS),
+            };
+            tokio::time::sleep(delay).await;
+        }
+    });
+}
 
-                println!("[CLIENTE] Conexão encerrada.");
-            }
-            Err(e) => {
-                eprintln!("[CLIENTE] Falha ao conectar: {e}");
+// fica conectado no líder até a conexão cair, o heartbeat parar ou a liderança mudar;
+// retorna false se nem conseguiu conectar
+async fn follow_leader(conn: &mut ConnectionManager, lease: &Lease) -> bool {
+    let url = lease.url.as_str();
+    let mut request = match url.into_client_request() {
+        Ok(request) => request,
+        Err(e) => {
+            eprintln!("[CLIENTE] URL do líder inválida {url}: {e}");
+            return false;
+        }
+    };
+    sign_handshake(&mut request);
+    let ws_stream = match connect_async_tls_with_config(request, None, false, TLS_CONNECTOR.clone()).await {
+        Ok((ws_stream, _)) => ws_stream,
+        Err(e) => {
+            eprintln!("[CLIENTE] Falha ao conectar em {url}: {e}");
+            return false;
+        }
+    };
+    println!("[CLIENTE] Conectado com sucesso em {url}!");
+    let (mut write, mut read) = ws_stream.split();
+
+    let mut reporter_conn = conn.clone();
+    let reporter = tokio::spawn(async move {
+        let mut ping = tokio::time::interval(Duration::from_millis(*WS_PING_INTERVAL_MS));
+        let mut report = tokio::time::interval(Duration::from_millis(*WS_REPORT_INTERVAL_MS));
+        'connection: loop {
+            let messages = tokio::select! {
+                _ = ping.tick() => vec![Message::Ping(Vec::new())],
+                _ = report.tick() => report_frames(&mut reporter_conn).await.into_iter().map(Message::text).collect(),
+            };
+            for message in messages {
+                if write.send(message).await.is_err() {
+                    break 'connection;
+                }
             }
         }
     });
+
+    let mut last_seen = Instant::now();
+    let heartbeat_timeout = Duration::from_millis(*WS_HEARTBEAT_TIMEOUT_MS);
+    let mut check = tokio::time::interval(Duration::from_millis(*LEADER_RENEW_MS));
+    loop {
+        tokio::select! {
+            msg = read.next() => match msg {
+                Some(Ok(msg)) => {
+                    last_seen = Instant::now();
+                    if msg.is_text() {
+                        if let Err(err) = apply_leader_frame(msg.to_text().unwrap()).await {
+                            eprintln!("[SYNC] {err}");
+                        }
+                    }
+                }
+                Some(Err(e)) => {
+                    eprintln!("[CLIENTE] Erro na conexão: {e}");
+                    break;
+                }
+                None => break,
+            },
+            _ = check.tick() => {
+                if last_seen.elapsed() > heartbeat_timeout {
+                    eprintln!("[CLIENTE] Líder sem responder há {:?}, reconectando.", last_seen.elapsed());
+                    break;
+                }
+                let still_leader = matches!(current_leader(conn).await, Ok(Some(current)) if current.owner == lease.owner);
+                if !still_leader || is_leader() {
+                    println!("[CLIENTE] Líder mudou, reconectando.");
+                    break;
+                }
+            }
+        }
+    }
+
+    reporter.abort();
+    println!("[CLIENTE] Conexão encerrada.");
+    true
 }
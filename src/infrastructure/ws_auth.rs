@@ -0,0 +1,307 @@
+use crate::infrastructure::config::{
+    INSTANCE_ID, WS_ALLOWED_ORIGINS, WS_AUTH_MAX_SKEW_MS, WS_SHARED_SECRET, WS_TLS_CA, WS_TLS_CERT, WS_TLS_KEY,
+};
+use crate::infrastructure::utils::now_unix_ms;
+use hmac::{Hmac, Mac};
+use once_cell::sync::Lazy;
+use sha2::Sha256;
+use std::fs::File;
+use std::io::BufReader;
+use std::sync::Arc;
+use tokio_rustls::rustls::pki_types::CertificateDer;
+use tokio_rustls::rustls::{ClientConfig, RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::Connector;
+use tungstenite::handshake::client::Request as ClientRequest;
+use tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use tungstenite::http::{HeaderValue, StatusCode};
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const INSTANCE_HEADER: &str = "x-ws-instance";
+pub const TIMESTAMP_HEADER: &str = "x-ws-timestamp";
+pub const SIGNATURE_HEADER: &str = "x-ws-signature";
+
+fn sign(secret: &str, data: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC aceita chave de qualquer tamanho");
+    mac.update(data);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+// comparação em tempo constante feita pelo próprio hmac
+fn verify(secret: &str, data: &[u8], signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC aceita chave de qualquer tamanho");
+    mac.update(data);
+    mac.verify_slice(&signature).is_ok()
+}
+
+// segredo, origens e janela de tempo; as funções públicas usam a configuração do ambiente
+pub struct WsAuth {
+    secret: Option<String>,
+    allowed_origins: Vec<String>,
+    max_skew_ms: u64,
+}
+
+static WS_AUTH: Lazy<WsAuth> = Lazy::new(|| {
+    WsAuth::new(WS_SHARED_SECRET.clone(), WS_ALLOWED_ORIGINS.clone(), *WS_AUTH_MAX_SKEW_MS)
+});
+
+impl WsAuth {
+    pub fn new(secret: Option<String>, allowed_origins: Vec<String>, max_skew_ms: u64) -> WsAuth {
+        WsAuth { secret, allowed_origins, max_skew_ms }
+    }
+
+    fn within_skew(&self, timestamp_ms: u64) -> bool {
+        now_unix_ms().abs_diff(timestamp_ms) <= self.max_skew_ms
+    }
+
+    pub fn sign_handshake(&self, instance: &str, request: &mut ClientRequest) {
+        let Some(secret) = self.secret.as_deref() else {
+            return;
+        };
+        let timestamp = now_unix_ms().to_string();
+        let signature = sign(secret, format!("{}:{}", instance, timestamp).as_bytes());
+        let headers = request.headers_mut();
+        for (name, value) in [(INSTANCE_HEADER, instance), (TIMESTAMP_HEADER, &timestamp), (SIGNATURE_HEADER, &signature)] {
+            if let Ok(value) = HeaderValue::from_str(value) {
+                headers.insert(name, value);
+            }
+        }
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn verify_handshake(&self, request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+        if let Some(origin) = request.headers().get("origin") {
+            let allowed = origin.to_str().map(|origin| self.allowed_origins.iter().any(|o| o == origin)).unwrap_or(false);
+            if !allowed {
+                return Err(reject(StatusCode::FORBIDDEN, "origem não permitida"));
+            }
+        }
+
+        let Some(secret) = self.secret.as_deref() else {
+            return Ok(response);
+        };
+        let header = |name: &str| request.headers().get(name).and_then(|value| value.to_str().ok());
+        let (Some(instance), Some(timestamp), Some(signature)) = (header(INSTANCE_HEADER), header(TIMESTAMP_HEADER), header(SIGNATURE_HEADER)) else {
+            return Err(reject(StatusCode::UNAUTHORIZED, "handshake sem assinatura"));
+        };
+        if !timestamp.parse::<u64>().map(|timestamp| self.within_skew(timestamp)).unwrap_or(false) {
+            return Err(reject(StatusCode::UNAUTHORIZED, "timestamp fora da janela"));
+        }
+        if !verify(secret, format!("{}:{}", instance, timestamp).as_bytes(), signature) {
+            return Err(reject(StatusCode::UNAUTHORIZED, "assinatura inválida"));
+        }
+        Ok(response)
+    }
+
+    pub fn seal_frame(&self, json: String) -> String {
+        match self.secret.as_deref() {
+            Some(secret) => format!("{}.{}", sign(secret, json.as_bytes()), json),
+            None => json,
+        }
+    }
+
+    pub fn open_frame<'a>(&self, frame: &'a str) -> Result<&'a str, String> {
+        let Some(secret) = self.secret.as_deref() else {
+            return Ok(frame);
+        };
+        let (signature, json) = frame.split_once('.').ok_or("frame sem assinatura")?;
+        if !verify(secret, json.as_bytes(), signature) {
+            return Err("assinatura do frame inválida".to_string());
+        }
+        Ok(json)
+    }
+
+    pub fn check_freshness(&self, sent_at: u64) -> Result<(), String> {
+        if self.secret.is_some() && !self.within_skew(sent_at) {
+            return Err(format!("mensagem fora da janela de tempo (sentAt {})", sent_at));
+        }
+        Ok(())
+    }
+}
+
+pub fn auth_enabled() -> bool {
+    WS_AUTH.secret.is_some()
+}
+
+// cabeçalhos do handshake: quem é a instância e a assinatura de "instância:timestamp"
+pub fn sign_handshake(request: &mut ClientRequest) {
+    WS_AUTH.sign_handshake(INSTANCE_ID.as_str(), request)
+}
+
+fn reject(status: StatusCode, reason: &str) -> ErrorResponse {
+    let mut response = ErrorResponse::new(Some(reason.to_string()));
+    *response.status_mut() = status;
+    response
+}
+
+// callback do accept_hdr_async (a assinatura é imposta pelo tungstenite): recusa origens
+// de navegador não listadas e handshakes sem assinatura válida
+#[allow(clippy::result_large_err)]
+pub fn verify_handshake(request: &Request, response: Response) -> Result<Response, ErrorResponse> {
+    WS_AUTH.verify_handshake(request, response)
+}
+
+// com segredo, cada frame vai como "<hmac hex>.<json>"
+pub fn seal_frame(json: String) -> String {
+    WS_AUTH.seal_frame(json)
+}
+
+pub fn open_frame(frame: &str) -> Result<&str, String> {
+    WS_AUTH.open_frame(frame)
+}
+
+// mensagens assinadas também precisam ser recentes, senão dá pra repetir uma captura antiga
+pub fn check_freshness(sent_at: u64) -> Result<(), String> {
+    WS_AUTH.check_freshness(sent_at)
+}
+
+fn load_certs(path: &str) -> Result<Vec<CertificateDer<'static>>, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+    rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{}: {}", path, e))
+}
+
+fn build_acceptor(cert_path: &str, key_path: &str) -> Result<TlsAcceptor, String> {
+    let certs = load_certs(cert_path)?;
+    let key_file = File::open(key_path).map_err(|e| format!("{}: {}", key_path, e))?;
+    let key = rustls_pemfile::private_key(&mut BufReader::new(key_file))
+        .map_err(|e| format!("{}: {}", key_path, e))?
+        .ok_or(format!("{}: nenhuma chave privada encontrada", key_path))?;
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| e.to_string())?;
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+fn build_connector(ca_path: &str) -> Result<Connector, String> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(ca_path)? {
+        roots.add(cert).map_err(|e| e.to_string())?;
+    }
+    let config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(Connector::Rustls(Arc::new(config)))
+}
+
+// TLS no listener só quando certificado e chave estão configurados; erro de configuração derruba o processo
+pub static TLS_ACCEPTOR: Lazy<Option<TlsAcceptor>> = Lazy::new(|| {
+    let (cert, key) = (WS_TLS_CERT.as_deref()?, WS_TLS_KEY.as_deref()?);
+    Some(build_acceptor(cert, key).unwrap_or_else(|e| panic!("Falha ao carregar TLS do WebSocket: {}", e)))
+});
+
+// sem CA própria, wss:// usa as raízes públicas (webpki-roots)
+pub static TLS_CONNECTOR: Lazy<Option<Connector>> = Lazy::new(|| {
+    let ca = WS_TLS_CA.as_deref()?;
+    Some(build_connector(ca).unwrap_or_else(|e| panic!("Falha ao carregar a CA do WebSocket: {}", e)))
+});
+
+#[cfg(test)]
+mod tests {
+    use super::{WsAuth, INSTANCE_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+    use crate::infrastructure::utils::now_unix_ms;
+    use tungstenite::client::IntoClientRequest;
+    use tungstenite::handshake::server::{Request, Response};
+    use tungstenite::http::StatusCode;
+
+    const SECRET: &str = "segredo";
+
+    fn auth() -> WsAuth {
+        WsAuth::new(Some(SECRET.to_string()), vec!["https://painel.local".to_string()], 1_000)
+    }
+
+    // handshake assinado por quem tem o segredo, como o follower manda
+    fn signed_request(secret: &str) -> Request {
+        let mut request = "ws://lider:9001".into_client_request().unwrap();
+        WsAuth::new(Some(secret.to_string()), vec![], 1_000).sign_handshake("api-1", &mut request);
+        request.map(|_| ())
+    }
+
+    fn status(result: Result<Response, tungstenite::handshake::server::ErrorResponse>) -> Option<StatusCode> {
+        result.err().map(|response| response.status())
+    }
+
+    #[test]
+    fn handshake_signed_with_the_secret_is_accepted() {
+        assert!(auth().verify_handshake(&signed_request(SECRET), Response::default()).is_ok());
+    }
+
+    #[test]
+    fn handshake_without_headers_is_rejected() {
+        let request = "ws://lider:9001".into_client_request().unwrap().map(|_| ());
+        assert_eq!(status(auth().verify_handshake(&request, Response::default())), Some(StatusCode::UNAUTHORIZED));
+
+        let mut request = signed_request(SECRET);
+        request.headers_mut().remove(SIGNATURE_HEADER);
+        assert_eq!(status(auth().verify_handshake(&request, Response::default())), Some(StatusCode::UNAUTHORIZED));
+    }
+
+    #[test]
+    fn handshake_with_bad_signature_is_rejected() {
+        let request = signed_request("outro segredo");
+        assert_eq!(status(auth().verify_handshake(&request, Response::default())), Some(StatusCode::UNAUTHORIZED));
+
+        // assinatura válida, mas para outra instância
+        let mut request = signed_request(SECRET);
+        request.headers_mut().insert(INSTANCE_HEADER, "api-2".parse().unwrap());
+        assert_eq!(status(auth().verify_handshake(&request, Response::default())), Some(StatusCode::UNAUTHORIZED));
+    }
+
+    #[test]
+    fn handshake_with_expired_timestamp_is_rejected() {
+        let mut request = signed_request(SECRET);
+        let old = (now_unix_ms() - 60_000).to_string();
+        request.headers_mut().insert(TIMESTAMP_HEADER, old.parse().unwrap());
+        assert_eq!(status(auth().verify_handshake(&request, Response::default())), Some(StatusCode::UNAUTHORIZED));
+    }
+
+    #[test]
+    fn handshake_from_disallowed_origin_is_rejected() {
+        let mut request = signed_request(SECRET);
+        request.headers_mut().insert("origin", "https://evil.example".parse().unwrap());
+        assert_eq!(status(auth().verify_handshake(&request, Response::default())), Some(StatusCode::FORBIDDEN));
+
+        request.headers_mut().insert("origin", "https://painel.local".parse().unwrap());
+        assert!(auth().verify_handshake(&request, Response::default()).is_ok());
+
+        // sem segredo a origem continua sendo checada
+        let open = WsAuth::new(None, vec![], 1_000);
+        request.headers_mut().insert("origin", "https://evil.example".parse().unwrap());
+        assert_eq!(status(open.verify_handshake(&request, Response::default())), Some(StatusCode::FORBIDDEN));
+    }
+
+    #[test]
+    fn sealed_frame_opens_only_untampered() {
+        let auth = auth();
+        let sealed = auth.seal_frame(r#"{"version":1}"#.to_string());
+        assert_eq!(auth.open_frame(&sealed), Ok(r#"{"version":1}"#));
+
+        let tampered = sealed.replace(r#""version":1"#, r#""version":2"#);
+        assert!(auth.open_frame(&tampered).is_err());
+        assert!(auth.open_frame(r#"{"version":1}"#).is_err());
+        assert!(WsAuth::new(Some("outro".to_string()), vec![], 1_000).open_frame(&sealed).is_err());
+    }
+
+    #[test]
+    fn frames_pass_through_without_secret() {
+        let open = WsAuth::new(None, vec![], 1_000);
+        assert_eq!(open.seal_frame("{}".to_string()), "{}");
+        assert_eq!(open.open_frame("{}"), Ok("{}"));
+    }
+
+    #[test]
+    fn freshness_is_checked_only_with_secret() {
+        let now = now_unix_ms();
+        assert!(auth().check_freshness(now).is_ok());
+        assert!(auth().check_freshness(now - 500).is_ok());
+        assert!(auth().check_freshness(now - 5_000).is_err());
+        assert!(auth().check_freshness(now + 5_000).is_err());
+        assert!(WsAuth::new(None, vec![], 1_000).check_freshness(0).is_ok());
+    }
+}
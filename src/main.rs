@@ -1,143 +1,210 @@
-use axum::{
-    routing::{get, post}
-    , Router,
-};
-use redis::aio::ConnectionManager;
-use reqwest::Client;
-use rinha2025::api::handlers::{payments, payments_summary};
-use rinha2025::application::process;
-use rinha2025::domain::entities::{AppState, PostPayments, ProcessorDecision};
-use rinha2025::infrastructure::config::INSTANCE_ROLE;
-use rinha2025::infrastructure::health::{get_best_processor, start_service_health};
-use rinha2025::infrastructure::redis::get_redis_connection;
-use rinha2025::infrastructure::{run_master, run_slave};
-use std::env;
-use std::sync::Arc;
-use std::thread::sleep;
-use axum::body::{Bytes};
-use tokio::sync::{mpsc, Mutex, Semaphore};
-
-#[tokio::main]
-async fn main() {
-    /*tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env().add_directive("info".parse().unwrap()))
-        .init();*/
-
-    if INSTANCE_ROLE.as_str() == "master" {
-        start_service_health();
-        run_master().await;
-    }
-    if INSTANCE_ROLE.as_str() == "slave" {
-        run_slave().await;
-    }
-    let workers = std::env::var("MAX_WORKERS")
-        .ok()
-        .and_then(|s| s.parse::<usize>().ok())
-        .unwrap_or(2);
-
-    let (tx, rx) = mpsc::unbounded_channel::<Bytes>();
-    let tx_for_worker = tx.clone();
-    let port = env::var("PORT").unwrap_or("9999".to_string());
-    let client = Arc::new(Client::builder()
-        //.timeout(Duration::from_millis(300))
-        .build()
-        .unwrap());
-
-    let connection : Arc<ConnectionManager> = match get_redis_connection().await {
-        Ok(conn) => Arc::new(conn),
-        Err(e) => {
-            eprintln!("Falha ao conectar no redis: {:?}", e);
-            return;
-        }
-    };
-
-    let rx = Arc::new(Mutex::new(rx));
-    for _ in 0..workers {
-        let connection_for_worker = Arc::clone(&connection);
-        let client_clone = Arc::clone(&client);
-        let rx_clone = Arc::clone(&rx);
-        let tx_for_worker = tx_for_worker.clone();
-
-
-        tokio::spawn(async move {
-            let client = client_clone;
-            let conn_clone = Arc::clone(&connection_for_worker);
-
-            loop {
-                let decision = get_best_processor().await;
-                if decision == ProcessorDecision::FAILING {
-                    //eprintln!("Processor em estado FAILING. Aguardando...");
-                    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
-                    continue;
-                }
-                // trava o mutex só enquanto faz o recv
-                let maybe_payment = {
-                    let mut rx_guard = rx_clone.lock().await;
-                    rx_guard.recv().await
-                };
-                if let Some(bytes) = maybe_payment {
-                    if let Ok(post_payments) = serde_json::from_slice::<PostPayments>(&bytes) {
-                        let payload = serde_json::to_string(&post_payments).unwrap();
-                        if let Err(e) = process(payload, conn_clone.clone(), client.clone(), decision).await {
-                            eprintln!("Erro ao processar pagamento: {:?}", e);
-                            if let Err(e) = tx_for_worker.send(bytes) {
-                                eprintln!("Erro ao tentar colocar o pagamento na fila novamente: {:?}", e);
-                            } else {
-                                eprintln!("Pagamento recolocado na fila.");
-                            }
-                        }
-                    }
-                }else{
-                    break;
-                }
-            }
-        });
-    }
-
-
-
-
-
-    /*
-    let connection_for_worker = Arc::clone(&connection);
-    tokio::spawn(async move {
-        let client = Arc::clone(&client);
-        let conn_clone = Arc::clone(&connection_for_worker);
-        while let Some(bytes) = rx.recv().await {
-            if let Ok(post_payments) = serde_json::from_slice::<PostPayments>(&bytes) {
-                let decision = get_best_processor().await;
-                if decision == ProcessorDecision::FAILING {
-                    eprintln!("Processor em estado FAILING. Aguardando...");
-                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
-                    continue;
-                }
-                let payload = serde_json::to_string(&post_payments).unwrap();
-                if let Err(e) = process(payload, conn_clone.clone(), client.clone(), decision).await {
-                    eprintln!("Erro ao processar pagamento: {:?}", e);
-                    if let Err(e) = tx_for_worker.send(bytes) {
-                        eprintln!("Erro ao tentar colocar o pagamento na fila novamente: {:?}", e);
-                    } else {
-                        eprintln!("Pagamento recolocado na fila.");
-                    }
-                }
-            }
-        }
-    });
-    */
-
-    let app = Router::new()
-        .route("/payments", post(payments))
-        .route("/payments-summary", get(payments_summary))
-        /*.layer(
-            TraceLayer::new_for_http()
-                .make_span_with(DefaultMakeSpan::new().level(Level::INFO))
-                .on_response(DefaultOnResponse::new().level(Level::INFO)),
-        )*/
-        .with_state(AppState {
-            redis: Arc::clone(&connection),
-            sender: tx
-        });
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port)).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
-}
-
+use axum::{
+    middleware,
+    routing::{get, post}
+    , Router,
+};
+use reqwest::Client;
+use rinha2025::api::handlers::{
+    discard_failed_payment, discard_failed_payments, get_failed_payment, get_payment, list_failed_payments,
+    list_payments, metrics, payments, payments_summary, replay_failed_payment, replay_failed_payments,
+};
+use rinha2025::api::require_admin_token;
+use rinha2025::application::process;
+use rinha2025::domain::entities::{AppState, AttemptRecord, DeadLetter, ProcessorDecision, QueuedPayment};
+use rinha2025::infrastructure::config::{
+    ADMIN_TOKEN, MAX_PAYMENT_ATTEMPTS, PROCESSOR_TIMEOUT_CEILING_MS, PROCESSOR_TIMEOUT_FLOOR_MS, SUMMARY_BUCKET_MS,
+};
+use rinha2025::infrastructure::health::{get_best_processor, start_service_health};
+use rinha2025::infrastructure::{
+    adopt_bucket_width, bootstrap_from_snapshot, claim_instance_id, open_backend, owns_instance_id, run_standalone,
+    start_delayed_promoter, start_leader_election, start_stats_refresher, HEALTH_BROADCASTER,
+};
+use rinha2025::infrastructure::utils::now_unix_ms;
+use chrono::{SecondsFormat, Utc};
+use once_cell::sync::Lazy;
+use std::env;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::main]
+async fn main() {
+    /*tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env().add_directive("info".parse().unwrap()))
+        .init();*/
+
+    let workers = std::env::var("MAX_WORKERS")
+        .ok()
+        .and_then(|s| s.parse::<usize>().ok())
+        .unwrap_or(2);
+
+    let port = env::var("PORT").unwrap_or("9999".to_string());
+    // o timeout de cada requisição é calculado por processador, aqui fica só o teto;
+    // lê o piso agora para um piso acima do teto falhar na subida e não no primeiro pagamento
+    Lazy::force(&PROCESSOR_TIMEOUT_FLOOR_MS);
+    let client = Arc::new(Client::builder()
+        .timeout(Duration::from_millis(*PROCESSOR_TIMEOUT_CEILING_MS))
+        .build()
+        .unwrap());
+
+    let backend = match open_backend().await {
+        Ok(backend) => backend,
+        Err(e) => {
+            eprintln!("Falha ao abrir o armazenamento de pagamentos: {}", e);
+            return;
+        }
+    };
+
+    match backend.redis.clone() {
+        Some(connection) => {
+            // antes de recuperar a fila: a lista de processamento é do INSTANCE_ID
+            if let Err(e) = claim_instance_id(&mut connection.clone()).await {
+                eprintln!("{}", e);
+                return;
+            }
+            match adopt_bucket_width(&mut connection.clone()).await {
+                Ok(true) => println!("[SUMMARY] Buckets do summary passam a usar a largura de {}ms", *SUMMARY_BUCKET_MS),
+                Ok(false) => {}
+                Err(e) => eprintln!("[SUMMARY] Erro ao gravar a largura dos buckets: {}", e),
+            }
+            // a liderança dos health checks é decidida pelo lease no redis, qualquer instância pode assumir
+            start_leader_election(connection.clone());
+            start_service_health(Some(connection.clone()));
+            match bootstrap_from_snapshot(&mut connection.clone()).await {
+                Ok(true) => println!("Health inicial carregado do último snapshot"),
+                Ok(false) => {}
+                Err(e) => eprintln!("Erro ao carregar o snapshot de health: {}", e),
+            }
+            HEALTH_BROADCASTER.start(connection);
+        }
+        None => {
+            run_standalone();
+            start_service_health(None);
+        }
+    }
+
+    match backend.queue.recover_in_flight().await {
+        Ok(0) => {}
+        Ok(recovered) => println!("{} pagamentos em processamento devolvidos para a fila", recovered),
+        Err(e) => eprintln!("Erro ao recuperar pagamentos em processamento: {}", e),
+    }
+
+    start_delayed_promoter(Arc::clone(&backend.queue));
+    start_stats_refresher();
+    let store = Arc::clone(&backend.store);
+
+    for _ in 0..workers {
+        // fila exclusiva do worker, no redis o BLMOVE bloqueia a conexão enquanto espera
+        let queue = match backend.queue.for_worker().await {
+            Ok(queue) => queue,
+            Err(e) => {
+                eprintln!("Falha ao abrir a fila do worker: {}", e);
+                return;
+            }
+        };
+        let store_for_worker = Arc::clone(&store);
+        let client_clone = Arc::clone(&client);
+
+        tokio::spawn(async move {
+            let client = client_clone;
+
+            // se perder o INSTANCE_ID termina o item em andamento e não pega mais nenhum
+            while owns_instance_id() {
+                let decision = get_best_processor().await;
+                if decision == ProcessorDecision::FAILING {
+                    //eprintln!("Processor em estado FAILING. Aguardando...");
+                    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                    continue;
+                }
+                let bytes = match queue.claim(Duration::from_secs(1)).await {
+                    Ok(Some(bytes)) => bytes,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        eprintln!("Erro ao ler a fila de pagamentos: {}", e);
+                        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                        continue;
+                    }
+                };
+                let mut queued = match serde_json::from_slice::<QueuedPayment>(&bytes) {
+                    Ok(queued) => queued,
+                    Err(e) => {
+                        eprintln!("Pagamento inválido descartado: {:?}", e);
+                        let _ = queue.ack(&bytes).await;
+                        continue;
+                    }
+                };
+                match process(&mut queued, store_for_worker.clone(), client.clone(), decision).await {
+                    Ok(()) => {
+                        if let Err(e) = queue.ack(&bytes).await {
+                            eprintln!("Erro ao confirmar pagamento processado: {}", e);
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("Erro ao processar pagamento: {}", e);
+                        queued.attempts += 1;
+                        queued.history.push(AttemptRecord {
+                            processor: e.processor.clone(),
+                            outcome: e.outcome,
+                            reason: e.reason.clone(),
+                            at: now_unix_ms(),
+                        });
+                        let retry_after = e.retry_after.filter(|_| queued.attempts < *MAX_PAYMENT_ATTEMPTS);
+                        if let Some(delay) = retry_after {
+                            // o worker não fica dormindo: o item vai para o ZSET de atrasados
+                            // e o promoter devolve para a fila quando chegar a hora
+                            let payload = serde_json::to_vec(&queued).unwrap();
+                            let due_ms = now_unix_ms() + delay.as_millis() as u64;
+                            if let Err(e) = queue.schedule_retry(&bytes, &payload, due_ms).await {
+                                eprintln!("Erro ao agendar nova tentativa do pagamento: {}", e);
+                            }
+                        } else {
+                            let entry = DeadLetter {
+                                payment: queued.payment,
+                                reason: e.reason,
+                                attempts: queued.attempts,
+                                last_processor: e.processor,
+                                failed_at: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+                                requested_at: queued.requested_at,
+                                history: queued.history,
+                            };
+                            if let Err(e) = queue.dead_letter(&bytes, &entry).await {
+                                eprintln!("Erro ao mover pagamento para a fila de falhas: {}", e);
+                            } else {
+                                eprintln!("Pagamento {} movido para a fila de falhas.", entry.payment.correlation_id);
+                            }
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    if ADMIN_TOKEN.is_none() {
+        println!("[ADMIN] ADMIN_TOKEN não definido, rotas /admin recusam todas as chamadas");
+    }
+    let admin = Router::new()
+        .route("/admin/metrics", get(metrics))
+        .route("/admin/payments", get(list_payments))
+        .route("/admin/payments/{correlation_id}", get(get_payment))
+        .route("/admin/failed-payments", get(list_failed_payments).delete(discard_failed_payments))
+        .route("/admin/failed-payments/replay", post(replay_failed_payments))
+        .route("/admin/failed-payments/{correlation_id}", get(get_failed_payment).delete(discard_failed_payment))
+        .route("/admin/failed-payments/{correlation_id}/replay", post(replay_failed_payment))
+        .route_layer(middleware::from_fn(require_admin_token));
+
+    let app = Router::new()
+        .route("/payments", post(payments))
+        .route("/payments-summary", get(payments_summary))
+        .merge(admin)
+        /*.layer(
+            TraceLayer::new_for_http()
+                .make_span_with(DefaultMakeSpan::new().level(Level::INFO))
+                .on_response(DefaultOnResponse::new().level(Level::INFO)),
+        )*/
+        .with_state(AppState {
+            store,
+            queue: backend.queue,
+        });
+    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port)).await.unwrap();
+    axum::serve(listener, app).await.unwrap();
+}
+